@@ -1,4 +1,17 @@
 #![cfg(not(test))]
+use std::sync::{mpsc::{self, Sender}, Mutex};
+use std::thread::{self, JoinHandle};
+
+use log::{debug, error};
+
+/// Sounds are played one after another on their own thread, so a long sound does not stall the daemon loop
+struct AudioWorker {
+    sender: Sender<String>,
+    thread: JoinHandle<()>,
+}
+
+static AUDIO_WORKER: Mutex<Option<AudioWorker>> = Mutex::new(None);
+
 /// Plays sound along side notification
 pub fn play_notification_sound(sound_path: &str) {
     
@@ -7,11 +20,34 @@ pub fn play_notification_sound(sound_path: &str) {
         return;
     }
 
+    let Ok(mut audio_worker) = AUDIO_WORKER.lock() else { return };
+    let audio_worker = audio_worker.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel::<String>();
+        let thread = thread::spawn(move || receiver.iter().for_each(|sound_path| play_sound(&sound_path)));
+        AudioWorker { sender, thread }
+    });
+    if audio_worker.sender.send(sound_path.to_string()).is_err() {
+        error!("Could not play audio file, the audio worker stopped");
+    }
+}
+
+/// Waits until every queued sound finished playing, called on shutdown
+pub fn drain_audio_worker() {
+    let Some(audio_worker) = AUDIO_WORKER.lock().ok().and_then(|mut audio_worker| audio_worker.take()) else { return };
+    // The worker stops once the channel is closed and empty
+    drop(audio_worker.sender);
+    if audio_worker.thread.join().is_err() {
+        error!("The audio worker panicked");
+    }
+}
+
+fn play_sound(sound_path: &str) {
+    debug!("Playing {sound_path}");
+
     let stream_handle = match rodio::OutputStreamBuilder::open_default_stream(){
         Ok(stream_handle) => stream_handle,
         Err(error) => {
-            eprintln!("Could not open Output Stream");
-            eprintln!("\t {:?}", error);
+            error!("Could not open Output Stream\n\t {error:?}");
             return;
         } 
     };
@@ -21,8 +57,7 @@ pub fn play_notification_sound(sound_path: &str) {
     let file = match std::fs::File::open(sound_path){
         Ok(file) => file,
         Err(error) => {
-            eprintln!("Could not open audio file");
-            eprintln!("\t {:?}", error);
+            error!("Could not open audio file {sound_path}\n\t {error:?}");
             return;
         }
     };
@@ -30,8 +65,7 @@ pub fn play_notification_sound(sound_path: &str) {
     let source = match rodio::Decoder::try_from(file) {
         Ok(source) => source,
         Err(error) => {
-            eprintln!("Could not play audio file");
-            eprintln!("\t {:?}", error);
+            error!("Could not play audio file {sound_path}\n\t {error:?}");
             return;
         }
     };
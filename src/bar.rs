@@ -0,0 +1,350 @@
+use std::{collections::{BTreeMap, HashSet}, io::{self, Write}, path::PathBuf, thread, time::Duration};
+
+use log::error;
+use notify_rust::Urgency;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::battery_monitor::{self, BatteryStats};
+use crate::config::{layer_field, BatteryNotification, Config, ConfigIssue, GetUrgency, DEFAULT_PROFILE};
+use crate::config_file_manager::handle_config_file;
+use crate::control_socket::{self, send_request, watch_events, ControlError, DaemonStatus, Request};
+use crate::state;
+use crate::status::{battery_status, format_time_remaining};
+
+const DEFAULT_FORMAT: &str = "{percentage}%";
+const DEFAULT_TOOLTIP_FORMAT: &str = "{state}, {time_remaining}\nProfile: {profile}";
+const PLACEHOLDERS: [&str; 4] = ["percentage", "state", "time_remaining", "profile"];
+
+// ----------------------------------------------------------------
+// Bar Config Structs and Implementation
+// ----------------------------------------------------------------
+/// The `[bar]` table, used by `spider-volt bar`
+/// e.g. `{ format = "{percentage}% {time_remaining}", critical = { color = "#ff0000" } }`
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct BarConfig {
+    /// Text shown in the bar, placeholders: {percentage}, {state}, {time_remaining}, {profile}
+    format: Option<String>,
+    /// Waybar tooltip, same placeholders as `format`
+    tooltip_format: Option<String>,
+    /// Style while charging
+    charging: Option<BarStyle>,
+    /// Style while discharging above every low battery threshold
+    discharging: Option<BarStyle>,
+    /// Style while discharging at or below a low battery threshold
+    warning: Option<BarStyle>,
+    /// Style while discharging at or below a low battery threshold with Critical urgency
+    critical: Option<BarStyle>,
+}
+
+/// How the bar looks at one level, the class is for Waybar CSS, the color for i3blocks and Polybar
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct BarStyle {
+    /// Defaults to the name of the level
+    class: Option<String>,
+    /// "#rrggbb" or "#rrggbbaa"
+    color: Option<String>,
+}
+
+/// Which style the bar uses, follows the low battery notifications so both change at the same percentage
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarLevel {
+    Charging,
+    Discharging,
+    Warning,
+    Critical,
+}
+
+impl BarLevel {
+    fn name(&self) -> &'static str {
+        match self {
+            BarLevel::Charging => "charging",
+            BarLevel::Discharging => "discharging",
+            BarLevel::Warning => "warning",
+            BarLevel::Critical => "critical",
+        }
+    }
+
+    /// Every threshold that was reached is red, Critical ones are told apart by their class
+    fn default_color(&self) -> Option<&'static str> {
+        match self {
+            BarLevel::Warning | BarLevel::Critical => Some("#ff5555"),
+            BarLevel::Charging | BarLevel::Discharging => None,
+        }
+    }
+}
+
+impl BarConfig {
+    pub fn is_empty(&self) -> bool {
+        self.format.is_none()
+            && self.tooltip_format.is_none()
+            && self.charging.is_none()
+            && self.discharging.is_none()
+            && self.warning.is_none()
+            && self.critical.is_none()
+    }
+
+    pub fn layer(&mut self, overlay: BarConfig) {
+        layer_field(&mut self.format, overlay.format);
+        layer_field(&mut self.tooltip_format, overlay.tooltip_format);
+        let styles = [
+            (&mut self.charging, overlay.charging),
+            (&mut self.discharging, overlay.discharging),
+            (&mut self.warning, overlay.warning),
+            (&mut self.critical, overlay.critical),
+        ];
+        for (style, overlay) in styles {
+            match (style.as_mut(), overlay) {
+                (_, None) => (),
+                (Some(style), Some(overlay)) => style.layer(overlay),
+                (None, Some(overlay)) => *style = Some(overlay),
+            }
+        }
+    }
+
+    /// Unknown placeholders and colors the bars cannot show
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = Vec::new();
+        for (key, format) in [("format", &self.format), ("tooltip_format", &self.tooltip_format)] {
+            for placeholder in format.iter().flat_map(|format| placeholders(format)) {
+                if !PLACEHOLDERS.contains(&placeholder) {
+                    issues.push(ConfigIssue::error(format!("bar.{key}"), format!(
+                        "unknown placeholder {{{placeholder}}}, use one of {}",
+                        PLACEHOLDERS.map(|placeholder| format!("{{{placeholder}}}")).join(", "))));
+                }
+            }
+        }
+        for level in [BarLevel::Charging, BarLevel::Discharging, BarLevel::Warning, BarLevel::Critical] {
+            if let Some(color) = self.style(level).and_then(|style| style.color.as_deref())
+                && !is_hex_color(color) {
+                issues.push(ConfigIssue::error(format!("bar.{}.color", level.name()),
+                    format!("\"{color}\" is not a color, use \"#rrggbb\" or \"#rrggbbaa\"")));
+            }
+        }
+        issues
+    }
+
+    fn style(&self, level: BarLevel) -> Option<&BarStyle> {
+        match level {
+            BarLevel::Charging => self.charging.as_ref(),
+            BarLevel::Discharging => self.discharging.as_ref(),
+            BarLevel::Warning => self.warning.as_ref(),
+            BarLevel::Critical => self.critical.as_ref(),
+        }
+    }
+
+    fn class(&self, level: BarLevel) -> &str {
+        self.style(level).and_then(|style| style.class.as_deref()).unwrap_or(level.name())
+    }
+
+    fn color(&self, level: BarLevel) -> Option<&str> {
+        self.style(level).and_then(|style| style.color.as_deref()).or(level.default_color())
+    }
+}
+
+impl BarStyle {
+    fn layer(&mut self, overlay: BarStyle) {
+        layer_field(&mut self.class, overlay.class);
+        layer_field(&mut self.color, overlay.color);
+    }
+}
+
+/// Names between braces, e.g. "percentage" in "{percentage}%"
+fn placeholders(format: &str) -> impl Iterator<Item = &str> {
+    format.split('{').skip(1).filter_map(|part| part.split_once('}').map(|(placeholder, _)| placeholder))
+}
+
+fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        (hex.len() == 6 || hex.len() == 8) && hex.chars().all(|character| character.is_ascii_hexdigit())
+    })
+}
+
+// ----------------------------------------------------------------
+// Bar Output
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarMode {
+    /// One JSON object per line with text, tooltip, class and percentage
+    Waybar,
+    /// One i3bar protocol block per line, for i3blocks with `format=json` and `interval=persist`
+    I3blocks,
+    /// One line of text with Polybar color tags, for a script module with `tail = true`
+    Polybar,
+}
+
+impl BarMode {
+    pub const NAMES: [&'static str; 3] = ["waybar", "i3blocks", "polybar"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "waybar" => Some(BarMode::Waybar),
+            "i3blocks" => Some(BarMode::I3blocks),
+            "polybar" => Some(BarMode::Polybar),
+            _ => None,
+        }
+    }
+}
+
+/// Discharging at or below a low battery threshold is a warning, or critical if that notification is Critical
+/// Takes the thresholds after `minimum_urgency` filtered them, so the level changes where a notification fires
+pub fn bar_level(status: &DaemonStatus, low_level_notifs: &Option<BTreeMap<u8, BatteryNotification>>) -> BarLevel {
+    if status.state == "Charging" {
+        return BarLevel::Charging;
+    }
+    // The lowest threshold reached is the one that fired last
+    let reached_threshold = low_level_notifs.iter()
+        .flatten()
+        .find(|(battery_level, _)| status.percentage <= **battery_level as i32);
+    match reached_threshold {
+        Some((_, notification_info)) if notification_info.urgency() == Urgency::Critical => BarLevel::Critical,
+        Some(_) => BarLevel::Warning,
+        None => BarLevel::Discharging,
+    }
+}
+
+/// One line of output for the bar
+pub fn render_bar(mode: BarMode, configuration: &Config, status: &DaemonStatus) -> String {
+    let bar_config = configuration.bar();
+    let level = bar_level(status, &configuration.low_battery_levels);
+    let text = expand_placeholders(bar_config.format.as_deref().unwrap_or(DEFAULT_FORMAT), status);
+
+    match mode {
+        BarMode::Waybar => json!({
+            "text": text,
+            "tooltip": expand_placeholders(bar_config.tooltip_format.as_deref().unwrap_or(DEFAULT_TOOLTIP_FORMAT), status),
+            "class": bar_config.class(level),
+            "percentage": status.percentage,
+        }).to_string(),
+        BarMode::I3blocks => {
+            let mut block = json!({ "full_text": text, "short_text": format!("{}%", status.percentage) });
+            if let Some(color) = bar_config.color(level) {
+                block["color"] = color.into();
+            }
+            block.to_string()
+        },
+        // Every line replaces the module's text, so the text has to stay on one line
+        BarMode::Polybar => {
+            let text = text.replace('\n', " ");
+            match bar_config.color(level) {
+                Some(color) => format!("%{{F{color}}}{text}%{{F-}}"),
+                None => text,
+            }
+        },
+    }
+}
+
+fn expand_placeholders(format: &str, status: &DaemonStatus) -> String {
+    format
+        .replace("{percentage}", &status.percentage.to_string())
+        .replace("{state}", &status.state)
+        .replace("{time_remaining}", &format_time_remaining(status))
+        .replace("{profile}", &status.profile)
+}
+
+// ----------------------------------------------------------------
+// Bar Loop
+// ----------------------------------------------------------------
+/// Prints a line whenever the output changes, the config follows the daemon's profile
+struct BarPrinter<'a> {
+    mode: BarMode,
+    config_files: &'a [PathBuf],
+    overrides: Option<&'a Config>,
+    /// The config and the profile it was loaded with
+    configuration: Option<(String, Config)>,
+    last_line: String,
+}
+
+impl BarPrinter<'_> {
+    /// Returns false once the bar closed the pipe, e.g. when Waybar or Polybar restarts
+    fn print(&mut self, status: &DaemonStatus) -> Result<bool, String> {
+        if self.configuration.as_ref().is_none_or(|(profile, _)| *profile != status.profile) {
+            let profile = (status.profile != DEFAULT_PROFILE).then_some(status.profile.as_str());
+            let configuration = handle_config_file(self.config_files, self.overrides, profile);
+            self.configuration = Some((status.profile.clone(), configuration));
+        }
+        let Some((_, configuration)) = &self.configuration else { return Ok(true) };
+
+        let line = render_bar(self.mode, configuration, status);
+        if line != self.last_line {
+            match writeln!(io::stdout().lock(), "{line}") {
+                Ok(()) => (),
+                Err(error) if error.kind() == io::ErrorKind::BrokenPipe => return Ok(false),
+                Err(error) => return Err(format!("Could not print the bar\n\t {error}")),
+            }
+            self.last_line = line;
+        }
+        Ok(true)
+    }
+
+    /// The config is loaded again with the next status
+    fn reload(&mut self) {
+        self.configuration = None;
+    }
+}
+
+/// Prints the bar until the daemon stops, or until the battery cannot be read with `standalone`
+/// Only returns once there is nothing left to print, the reason is printed
+/// Returns false if it stopped because of an error or the daemon stopped, true if the bar closed the pipe
+pub fn run_bar(mode: BarMode, standalone: bool, interval: u64, config_files: &[PathBuf], overrides: Option<&Config>) -> bool {
+    let mut bar_printer = BarPrinter { mode, config_files, overrides, configuration: None, last_line: String::new() };
+    let result = if standalone {
+        poll_battery(&mut bar_printer, Duration::from_secs(interval.max(1)))
+    } else {
+        follow_daemon(&mut bar_printer)
+    };
+    match result {
+        Ok(()) => true,
+        Err(error) => {
+            error!("{error}");
+            false
+        }
+    }
+}
+
+/// Every event can change the output, so the status is asked for again after each one
+fn follow_daemon(bar_printer: &mut BarPrinter) -> Result<(), String> {
+    let socket_path = control_socket::socket_path().ok_or(ControlError::NoRuntimeDir.to_string())?;
+    let events = watch_events(&socket_path).map_err(|error| match error {
+        ControlError::NotRunning(_) => format!("{error}\nUse --standalone to read the battery without the daemon"),
+        _ => error.to_string(),
+    })?;
+    let daemon_status = || send_request(&socket_path, &Request::Status)
+        .map_err(|error| error.to_string())?
+        .status
+        .ok_or_else(|| ControlError::InvalidResponse("the response has no status".to_string()).to_string());
+
+    if !bar_printer.print(&daemon_status()?)? {
+        return Ok(());
+    }
+    for event in events {
+        let event = event.map_err(|error| format!("Lost the connection to spider-volt: {error}"))?;
+        let event: serde_json::Value = serde_json::from_str(&event).unwrap_or_default();
+        if event["event"] == "config_reloaded" {
+            bar_printer.reload();
+        }
+        if !bar_printer.print(&daemon_status()?)? {
+            return Ok(());
+        }
+    }
+    Err("spider-volt stopped".to_string())
+}
+
+/// Reads the battery like `status --standalone`, thresholds and snooze are not known without the daemon
+fn poll_battery(bar_printer: &mut BarPrinter, interval: Duration) -> Result<(), String> {
+    // The battery helpers print what went wrong
+    let battery_error = || "Could not read the battery".to_string();
+    let manager = battery_monitor::init_battery_manager().ok_or_else(battery_error)?;
+    let mut battery = battery_monitor::init_battery(&manager).ok_or_else(battery_error)?;
+    let mut battery_stats = BatteryStats::new(&manager, &mut battery).ok_or_else(battery_error)?;
+
+    loop {
+        battery_stats.update_battery_stats(&manager, &mut battery);
+        let profile = state::load_active_profile().unwrap_or(DEFAULT_PROFILE.to_string());
+        if !bar_printer.print(&battery_status(&battery_stats, &profile, &HashSet::new(), None))? {
+            return Ok(());
+        }
+        thread::sleep(interval);
+    }
+}
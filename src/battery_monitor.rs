@@ -1,7 +1,11 @@
+use log::error;
+use notify_rust::Urgency;
 use serde::Deserialize;
 
 use crate::config::{BatteryNotification, ChargerNotification, GetUrgency};
-use std::{collections::{BTreeMap, HashSet}, io};
+use crate::events::{DaemonEvent, NotificationKind};
+use crate::state::NotificationState;
+use std::{collections::{BTreeMap, HashSet}, mem};
 
 #[cfg(not(test))]
 use crate::notifications::{send_battery_notification, send_charger_notification};
@@ -18,6 +22,12 @@ pub struct BatteryStats {
     last_notified_state: String,
     current_state: String, 
     percentage: i32, 
+    /// Seconds until empty while discharging or until full while charging, if the battery reports it
+    #[serde(default)]
+    time_remaining: Option<u64>,
+    /// Events since the last `take_events`, recorded where the decisions are made
+    #[serde(skip)]
+    events: Vec<DaemonEvent>,
 }
 
 impl BatteryStats {
@@ -27,6 +37,7 @@ impl BatteryStats {
         battery_manager.refresh(battery).ok()?;
         let current_state = battery_state_to_string(battery.state());
         let percentage = (battery.state_of_charge().value * 100.0) as i32;
+        let time_remaining = battery_time_remaining(battery);
         let previous_state = if current_state == "Discharging" {"Charging"} else {"Discharging"};
 
         Some(
@@ -34,7 +45,9 @@ impl BatteryStats {
             prev_state: previous_state.to_string(), 
             last_notified_state: current_state.clone(),
             current_state,
-            percentage
+            percentage,
+            time_remaining,
+            events: Vec::new(),
         })
     } 
     
@@ -42,10 +55,87 @@ impl BatteryStats {
         if let Ok(()) = battery_manager.refresh(battery) {
 
             self.current_state = battery_state_to_string(battery.state());
-            self.percentage = ((battery.state_of_charge()).value * 100.0) as i32;
+            let previous_percentage = mem::replace(&mut self.percentage, ((battery.state_of_charge()).value * 100.0) as i32);
+            self.time_remaining = battery_time_remaining(battery);
+            if self.percentage != previous_percentage {
+                self.events.push(DaemonEvent::PercentageChanged { percentage: self.percentage, previous_percentage });
+            }
         }
     } 
     
+    pub fn is_charging(&self) -> bool {
+        self.current_state == "Charging"
+    }
+
+    pub fn percentage(&self) -> i32 {
+        self.percentage
+    }
+
+    pub fn state(&self) -> &str {
+        &self.current_state
+    }
+
+    pub fn time_remaining(&self) -> Option<u64> {
+        self.time_remaining
+    }
+
+    /// What was already notified, saved so it is not notified again after a restart
+    pub fn notification_state(&self, battery_notif_sent: &HashSet<u8>) -> NotificationState {
+        NotificationState {
+            state: self.last_notified_state.clone(),
+            sent_thresholds: battery_notif_sent.iter().copied().collect(),
+        }
+    }
+
+    /// Restores the state saved before a restart, unless the charger state changed while the daemon was down
+    /// Thresholds the battery is no longer past, e.g. after charging for a while, are dropped and fire again
+    pub fn restore_notification_state(&mut self, saved: NotificationState, battery_notif_sent: &mut HashSet<u8>) -> Result<(), String> {
+        if saved.state != self.current_state {
+            return Err(format!("the battery was {} and is {} now", saved.state, self.current_state));
+        }
+        // previous state should never be unknown
+        if self.current_state == "Unknown" {
+            return Err("the battery state is unknown".to_string());
+        }
+
+        let percentage = self.percentage;
+        let still_past = |threshold: &u8| match self.current_state.as_str() {
+            "Discharging" => percentage <= i32::from(*threshold),
+            "Charging" => percentage >= i32::from(*threshold),
+            _ => false,
+        };
+        battery_notif_sent.extend(saved.sent_thresholds.into_iter().filter(still_past));
+        // Without this the first state change check would see a switch and clear the thresholds again
+        self.prev_state = self.current_state.clone();
+        self.last_notified_state = saved.state;
+        Ok(())
+    }
+
+    /// Returns and clears the events recorded since the last call
+    pub fn take_events(&mut self) -> Vec<DaemonEvent> {
+        mem::take(&mut self.events)
+    }
+
+    /// Sends the first low battery notification that would fire, so its icon, sound and urgency can be checked
+    /// Falls back to a plain notification if no low battery notifications are configured
+    pub fn send_test_notification(
+        &self,
+        low_level_notifs: &Option<BTreeMap<u8, BatteryNotification>>,
+        notif_time: i32
+    ) {
+        match low_level_notifs.as_ref().and_then(|low_charges| low_charges.values().next_back()) {
+            Some(notification_info) => send_battery_notification(
+                &self.percentage,
+                notification_info.notification_message(),
+                notification_info.notification_icon(),
+                notification_info.urgency(),
+                notification_info.notification_sound(),
+                notif_time
+            ),
+            None => send_battery_notification(&self.percentage, "Test Notification", "battery", Urgency::Normal, "", notif_time),
+        }
+    }
+
     pub fn handle_charger_notifications(
         &mut self, 
         charger_notif: &Option<ChargerNotification>, 
@@ -65,7 +155,12 @@ impl BatteryStats {
         // Notify only once per inferred state change
         if inferred_state != self.last_notified_state {
             
-            self.last_notified_state = inferred_state.to_string();
+            let previous_state = mem::replace(&mut self.last_notified_state, inferred_state.to_string());
+            self.events.push(DaemonEvent::StateChanged {
+                state: inferred_state.to_string(),
+                previous_state,
+                percentage: self.percentage,
+            });
             
             if let Some(charger_notifications) = charger_notif
                 && charger_notifications.should_notify_for_state(inferred_state) {
@@ -77,6 +172,11 @@ impl BatteryStats {
                         charger_notifications.sound_for_state(inferred_state),
                         notif_time
                     );
+                    self.events.push(DaemonEvent::NotificationSent {
+                        kind: NotificationKind::Charger,
+                        summary: inferred_state.to_string(),
+                        percentage: self.percentage,
+                    });
             }
         }
     }
@@ -116,6 +216,7 @@ impl BatteryStats {
                         notification_info.notification_sound(), 
                         notif_time
                     );
+                    self.record_threshold_events(NotificationKind::Low, *battery_level, notification_info);
                 }
             }
             
@@ -135,10 +236,20 @@ impl BatteryStats {
                         notification_info.notification_sound(), 
                         notif_time
                     );
+                    self.record_threshold_events(NotificationKind::High, *battery_level, notification_info);
                 }
             }
         }
     }
+
+    fn record_threshold_events(&mut self, kind: NotificationKind, threshold: u8, notification_info: &BatteryNotification) {
+        self.events.push(DaemonEvent::ThresholdCrossed { kind, threshold, percentage: self.percentage });
+        self.events.push(DaemonEvent::NotificationSent {
+            kind,
+            summary: notification_info.notification_message().to_string(),
+            percentage: self.percentage,
+        });
+    }
 }
 
 
@@ -149,7 +260,7 @@ pub fn init_battery_manager () -> Option<battery::Manager> {
     match battery::Manager::new(){
         Ok(manager) => Some(manager),
         Err(error) => {           
-            eprintln!("Unable to get battery manager\n\n\n {error}");
+            error!("Unable to get battery manager\n\t {error}");
             None
         }
     }
@@ -161,19 +272,25 @@ pub fn init_battery(battery_manager: &battery::Manager) -> Option<battery::Batte
     match batteries_iterator.next() {
         Some(Ok(battery)) => Some(battery),
         Some(Err(error)) => {
-            eprintln!("Unable to access battery information\n\n\n {error}");
+            error!("Unable to access battery information\n\t {error}");
             None
         }
         None => {
-            eprintln!(
-                "Unable to find any batteries\n\n\n {}",
-                io::Error::from(io::ErrorKind::NotFound)
-            );
+            error!("Unable to find any batteries");
             None
         }
     }
 }
 
+/// Time values of the battery crate are in seconds
+fn battery_time_remaining(battery: &battery::Battery) -> Option<u64> {
+    match battery.state() {
+        battery::State::Charging => battery.time_to_full(),
+        battery::State::Discharging => battery.time_to_empty(),
+        _ => None,
+    }.map(|time| time.value as u64)
+}
+
 fn battery_state_to_string(state: battery::State) -> String {
     // Using a match statement rather than the to_string() implementation of the battery crate
     // because it returns the strings in lowercase and they are needed in Title case for better formatting
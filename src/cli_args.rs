@@ -1,13 +1,64 @@
-use clap::{Arg, Command};
+use clap::{Arg, ArgMatches, Command};
+use log::LevelFilter;
 
-use crate::config_file_manager::get_config_dir;
+use crate::bar::BarMode;
+use crate::config_file_manager::{user_config_path, ConfigFormat};
+use crate::config_overrides::parse_threshold_message;
+use crate::logging::{verbosity_level, LogFormat};
+
+#[derive(Debug)]
+pub enum CliCommand {
+    /// Interactive wizard that creates the config file
+    Init { force: bool },
+    /// Edits the user's config file, keeping its comments
+    Config(ConfigCommand),
+    /// Prints what the running daemon is doing, or reads the battery itself with `standalone`
+    Status { json: bool, standalone: bool },
+    /// Streams the events of the running daemon as JSON lines
+    Watch,
+    /// Keeps printing the status formatted for a status bar, `interval` is in seconds and only used with `standalone`
+    Bar { mode: BarMode, standalone: bool, interval: u64 },
+    /// Installs, removes or prints the systemd user unit
+    Service(ServiceCommand),
+}
+
+#[derive(Debug)]
+pub enum ConfigCommand {
+    Get { key: String },
+    Set { key: String, value: String },
+    Unset { key: String },
+}
+
+#[derive(Debug)]
+pub enum ServiceCommand {
+    /// `enable` also enables and starts the unit
+    Install { enable: bool },
+    Uninstall,
+    Print,
+}
 
 #[derive(Debug)]
 pub struct CliArgs {
     pub config_path: String,
     pub print_config: bool,
+    pub show_origin: bool,
     pub print_config_template: bool,
     pub create_config: bool,
+    pub force: bool,
+    pub check_config: bool,
+    pub profile: Option<String>,
+    pub set_profile: Option<String>,
+    pub replace: bool,
+    pub format: ConfigFormat,
+    pub print_schema: bool,
+    /// (threshold, message) pairs from `--low` and `--high`
+    pub low_overrides: Vec<(u8, String)>,
+    pub high_overrides: Vec<(u8, String)>,
+    /// The log level from `-v` and `-q`, None if neither was passed
+    pub verbosity: Option<LevelFilter>,
+    /// None detects the format, see `LogFormat::detect`
+    pub log_format: Option<LogFormat>,
+    pub command: Option<CliCommand>,
 }
 
 pub fn parse_cli() -> CliArgs {
@@ -20,6 +71,7 @@ pub fn parse_cli() -> CliArgs {
                 .long("config")
                 .value_name("FILE")
                 .num_args(1)  
+                .global(true)
                 .help("Path to config file"),
         )
         .arg(
@@ -28,24 +80,293 @@ pub fn parse_cli() -> CliArgs {
                 .help("Print the current configuration and exit")
                 .action(clap::ArgAction::SetTrue)
         )
+        .arg(
+            Arg::new("show-origin")
+                .long("show-origin")
+                .help("Annotate every value printed by --print-config with the file it came from")
+                .requires("print-config")
+                .action(clap::ArgAction::SetTrue)
+        )
         .arg(
            Arg::new("print-config-template")
                 .long("print-config-template")
                 .help("Print the config template and exit")
                 .action(clap::ArgAction::SetTrue)
         )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .value_name("FORMAT")
+                .num_args(1)
+                .value_parser(ConfigFormat::NAMES)
+                .default_value("toml")
+                .help("Format used by --print-config and --print-config-template")
+        )
+        .arg(
+           Arg::new("print-schema")
+                .long("print-schema")
+                .help("Print a JSON Schema of the config file and exit")
+                .action(clap::ArgAction::SetTrue)
+        )
         .arg(
            Arg::new("create-config")
                 .long("create-config")
                 .help("Create '~/.config/spider-volt/config.toml' if missing and exit")
                 .action(clap::ArgAction::SetTrue)
         )
+        .arg(
+           Arg::new("force")
+                .long("force")
+                .help("Overwrite an existing config file with --create-config, the old file is kept as a .bak file")
+                .requires("create-config")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+           Arg::new("check-config")
+                .long("check-config")
+                .help("Check the config file for errors and exit, exits non-zero if errors are found")
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("profile")
+                .short('p')
+                .long("profile")
+                .value_name("NAME")
+                .num_args(1)
+                .help("Use a profile from the config file, the choice is kept across restarts")
+        )
+        .arg(
+            Arg::new("set-profile")
+                .long("set-profile")
+                .value_name("NAME")
+                .num_args(1)
+                .help("Switch the running daemon to a profile and exit, 'default' switches back to no profile")
+        )
+        .arg(
+            Arg::new("replace")
+                .long("replace")
+                .help("Stop the running spider-volt instance and take its place")
+                .conflicts_with_all(["set-profile", "print-config", "print-config-template", "print-schema", "create-config", "check-config"])
+                .action(clap::ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("low")
+                .long("low")
+                .value_name("THRESHOLD:MESSAGE")
+                .value_parser(parse_threshold_message)
+                .action(clap::ArgAction::Append)
+                .help("Add or override a low battery notification, e.g. --low 15:\"Battery low\", can be repeated")
+        )
+        .arg(
+            Arg::new("high")
+                .long("high")
+                .value_name("THRESHOLD:MESSAGE")
+                .value_parser(parse_threshold_message)
+                .action(clap::ArgAction::Append)
+                .help("Add or override a high battery notification, e.g. --high 80:\"Unplug now\", can be repeated")
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .conflicts_with("quiet")
+                .help("Log more, -v for debug and -vv for trace messages, RUST_LOG filters by module")
+                .action(clap::ArgAction::Count)
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .help("Log less, -q for warnings and errors only, -qq for errors only")
+                .action(clap::ArgAction::Count)
+        )
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .num_args(1)
+                .global(true)
+                .value_parser(LogFormat::NAMES)
+                .help("Format of the log on stderr, defaults to journal under systemd and text otherwise")
+        )
+        .subcommand(
+            Command::new("init")
+                .about("Create the config file by answering a few questions")
+                .arg(
+                    Arg::new("force")
+                        .long("force")
+                        .help("Overwrite an existing config file, the old file is kept as a .bak file")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Read or edit a value in the config file, e.g. low_battery_levels.20.message")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("get")
+                        .about("Print a value from the config file")
+                        .arg(Arg::new("key").value_name("KEY").required(true))
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Set a value in the config file, values that are not valid toml are set as strings")
+                        .arg(Arg::new("key").value_name("KEY").required(true))
+                        .arg(Arg::new("value").value_name("VALUE").required(true).allow_hyphen_values(true))
+                )
+                .subcommand(
+                    Command::new("unset")
+                        .about("Remove a value from the config file")
+                        .arg(Arg::new("key").value_name("KEY").required(true))
+                )
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Print the battery state, active profile, fired thresholds and snooze of the running daemon")
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .help("Print the status as a single line of JSON")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("standalone")
+                        .long("standalone")
+                        .help("Read the battery directly instead of asking the daemon, fired thresholds and snooze are unknown")
+                        .action(clap::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Stream the events of the running daemon as JSON lines, e.g. for eww or ags widgets")
+        )
+        .subcommand(
+            Command::new("bar")
+                .about("Keep printing the battery status for Waybar, i3blocks or Polybar, styled by the [bar] config")
+                .arg(
+                    Arg::new("mode")
+                        .long("mode")
+                        .value_name("MODE")
+                        .num_args(1)
+                        .value_parser(BarMode::NAMES)
+                        .default_value("waybar")
+                        .help("Output format of each line")
+                )
+                .arg(
+                    Arg::new("standalone")
+                        .long("standalone")
+                        .help("Read the battery directly instead of following the daemon")
+                        .action(clap::ArgAction::SetTrue)
+                )
+                .arg(
+                    Arg::new("interval")
+                        .long("interval")
+                        .value_name("SECONDS")
+                        .num_args(1)
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .default_value("5")
+                        .requires("standalone")
+                        .help("How often the battery is read with --standalone")
+                )
+        )
+        .subcommand(
+            Command::new("service")
+                .about("Install, remove or print a systemd user unit that runs this binary")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("install")
+                        .about("Write the unit to $XDG_CONFIG_HOME/systemd/user")
+                        .arg(
+                            Arg::new("enable")
+                                .long("enable")
+                                .help("Also enable the unit and start it now")
+                                .action(clap::ArgAction::SetTrue)
+                        )
+                )
+                .subcommand(
+                    Command::new("uninstall")
+                        .about("Stop and disable the unit and remove it")
+                )
+                .subcommand(
+                    Command::new("print")
+                        .about("Print the unit instead of installing it")
+                )
+        )
         .get_matches();
 
+    let command = match matches.subcommand() {
+        Some(("init", init_matches)) => Some(CliCommand::Init {
+            force: *init_matches.get_one::<bool>("force").unwrap_or(&false),
+        }),
+        Some(("config", config_matches)) => parse_config_command(config_matches).map(CliCommand::Config),
+        Some(("status", status_matches)) => Some(CliCommand::Status {
+            json: *status_matches.get_one::<bool>("json").unwrap_or(&false),
+            standalone: *status_matches.get_one::<bool>("standalone").unwrap_or(&false),
+        }),
+        Some(("watch", _)) => Some(CliCommand::Watch),
+        Some(("bar", bar_matches)) => Some(CliCommand::Bar {
+            // clap already rejects names that are not in BarMode::NAMES
+            mode: bar_matches.get_one::<String>("mode")
+                .and_then(|mode| BarMode::from_name(mode))
+                .unwrap_or(BarMode::Waybar),
+            standalone: *bar_matches.get_one::<bool>("standalone").unwrap_or(&false),
+            interval: *bar_matches.get_one::<u64>("interval").unwrap_or(&5),
+        }),
+        Some(("service", service_matches)) => parse_service_command(service_matches).map(CliCommand::Service),
+        _ => None,
+    };
+
     CliArgs {
-        config_path: matches.get_one::<String>("config").cloned().unwrap_or(get_config_dir()),
+        config_path: matches.get_one::<String>("config").cloned().unwrap_or(user_config_path()),
         print_config: *matches.get_one::<bool>("print-config").unwrap_or(&false),
+        show_origin: *matches.get_one::<bool>("show-origin").unwrap_or(&false),
         print_config_template: *matches.get_one::<bool>("print-config-template").unwrap_or(&false),
         create_config: *matches.get_one::<bool>("create-config").unwrap_or(&false),
+        force: *matches.get_one::<bool>("force").unwrap_or(&false),
+        check_config: *matches.get_one::<bool>("check-config").unwrap_or(&false),
+        profile: matches.get_one::<String>("profile").cloned(),
+        set_profile: matches.get_one::<String>("set-profile").cloned(),
+        replace: *matches.get_one::<bool>("replace").unwrap_or(&false),
+        // clap already rejects names that are not in ConfigFormat::NAMES
+        format: matches.get_one::<String>("format")
+            .and_then(|format| ConfigFormat::from_name(format))
+            .unwrap_or(ConfigFormat::Toml),
+        print_schema: *matches.get_one::<bool>("print-schema").unwrap_or(&false),
+        low_overrides: matches.get_many::<(u8, String)>("low").into_iter().flatten().cloned().collect(),
+        high_overrides: matches.get_many::<(u8, String)>("high").into_iter().flatten().cloned().collect(),
+        verbosity: match (matches.get_count("verbose"), matches.get_count("quiet")) {
+            (0, 0) => None,
+            (verbose, quiet) => Some(verbosity_level(verbose, quiet)),
+        },
+        // clap already rejects names that are not in LogFormat::NAMES
+        log_format: matches.get_one::<String>("log-format").and_then(|log_format| LogFormat::from_name(log_format)),
+        command,
     }
-}
\ No newline at end of file
+}
+
+fn parse_config_command(matches: &ArgMatches) -> Option<ConfigCommand> {
+    // clap makes every argument required, so they are always present
+    let argument = |matches: &ArgMatches, name: &str| matches.get_one::<String>(name).cloned().unwrap_or_default();
+    match matches.subcommand()? {
+        ("get", get_matches) => Some(ConfigCommand::Get { key: argument(get_matches, "key") }),
+        ("set", set_matches) => Some(ConfigCommand::Set {
+            key: argument(set_matches, "key"),
+            value: argument(set_matches, "value"),
+        }),
+        ("unset", unset_matches) => Some(ConfigCommand::Unset { key: argument(unset_matches, "key") }),
+        _ => None,
+    }
+}
+
+fn parse_service_command(matches: &ArgMatches) -> Option<ServiceCommand> {
+    match matches.subcommand()? {
+        ("install", install_matches) => Some(ServiceCommand::Install {
+            enable: *install_matches.get_one::<bool>("enable").unwrap_or(&false),
+        }),
+        ("uninstall", _) => Some(ServiceCommand::Uninstall),
+        ("print", _) => Some(ServiceCommand::Print),
+        _ => None,
+    }
+}
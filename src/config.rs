@@ -1,52 +1,110 @@
-use serde::{Deserialize, Serialize};
+use log::warn;
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize};
 use notify_rust::Urgency;
-use std::{collections::BTreeMap};
+use std::{borrow::Cow, collections::{BTreeMap, HashSet}, fmt, path::Path, sync::Once};
+
+use crate::bar::BarConfig;
+use crate::path_expansion::{expand_file_path, expand_icon, expand_optional, ExpandPaths};
+use crate::profile_rules::ProfileRule;
 
 // ----------------------------------------------------------------
 // Configuration Struct and Implementation
 // ----------------------------------------------------------------
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Config {
+    /// How long notifications are shown for in ms, defaults to 5000
     notification_time: Option<i32>,
+    /// Disables every notification sound
+    mute_sounds: Option<bool>,
+    /// Only sends notifications with at least this urgency
+    minimum_urgency: Option<UrgencyLevel>,
+    /// Notifications sent while charging, keyed by battery percentage
     pub high_battery_levels: Option<BTreeMap<u8, BatteryNotification>>,
+    /// Notifications sent while discharging, keyed by battery percentage
     pub low_battery_levels: Option<BTreeMap<u8, BatteryNotification>>,
-    pub charger_notifications: Option<ChargerNotification>
+    /// Notifications sent when the charger is plugged in or unplugged
+    pub charger_notifications: Option<ChargerNotification>,
+    /// Fields inherited by every notification that does not set them
+    defaults: Option<Defaults>,
+    /// Named profiles, each one is layered on top of the rest of the config when it is active
+    profiles: Option<BTreeMap<String, Config>>,
+    /// Rules that activate a profile automatically, the first matching rule wins
+    profile_rules: Option<Vec<ProfileRule>>,
+    /// Send a notification when a rule switches the profile
+    announce_profile_switches: Option<bool>,
+    /// Output of `spider-volt bar`
+    bar: Option<BarConfig>,
+    /// Config files layered below this file, relative paths are relative to this file
+    #[serde(skip_serializing)]
+    include: Option<Vec<String>>,
+    /// Set by `Config::apply_profile`
+    #[serde(skip)]
+    active_profile: Option<String>,
 }
 
+/// Selecting this profile name means no profile is active
+pub const DEFAULT_PROFILE: &str = "default";
+
 /// This function reads a json config file and parses it into the Config Struct
 impl Config {
 
     /// This function creates a default config
     pub fn default() -> Self {
-        // The default config, sends a notification only at 20% battery
+        // The default config, sends a notification at 20% and at 100% battery
         let notification_time = Some(5000);
         let low_battery_levels = Some(BTreeMap::from([(20, 
             BatteryNotification { 
-                message: "Battery Low".to_string(), 
-                notification_icon: Some("".to_string()), 
-                notification_sound: Some("".to_string()), 
-                urgent_level: Some("".to_string()) 
+                message: Some("Battery Low".to_string()), 
+                notification_icon: None, 
+                notification_sound: None, 
+                urgent_level: None,
+                inherited: NotificationDefaults::default(),
             })]));
 
+        // The message is empty, so validation warns about it until a config file sets one
         let high_battery_levels = Some(BTreeMap::from([(100, 
             BatteryNotification { 
-                message: "".to_string(), 
-                notification_icon: Some("".to_string()), 
-                notification_sound: Some("".to_string()), 
-                urgent_level: Some("".to_string()) 
+                message: Some("".to_string()), 
+                notification_icon: None, 
+                notification_sound: None, 
+                urgent_level: None,
+                inherited: NotificationDefaults::default(),
             })]));
-        
-        let charger_notifications = Some (ChargerNotification {
+
+        let charger_notifications = Some(ChargerNotification {
             charging: Some(false),
-            plugged_sound: Some("".to_string()),
-            charging_icon: Some("".to_string()),
+            plugged_sound: None,
+            charging_icon: None,
             discharging: Some(false),
-            unplugged_sound: Some("".to_string()),
-            discharging_icon: Some("".to_string()),
-            urgent_level: Some("".to_string())
-            });
+            unplugged_sound: None,
+            discharging_icon: None,
+            urgent_level: None,
+            inherited: NotificationDefaults::default(),
+        });
 
-        Self { notification_time,high_battery_levels, low_battery_levels, charger_notifications }
+        Self { notification_time, high_battery_levels, low_battery_levels, charger_notifications,
+            defaults: None, mute_sounds: None, minimum_urgency: None, profiles: None, active_profile: None,
+            profile_rules: None, announce_profile_switches: None, bar: None, include: None }
+    }
+
+    /// This function creates a config template with the values the default config does not already set
+    /// used for the template printed and written by `--print-config-template` and `--create-config`
+    /// the annotated toml template in `config_template.rs` must have the same values
+    pub fn template() -> Self {
+        // Everything the defaults already set is commented out in the template, so it does not override lower layers
+        let high_battery_levels = Some(BTreeMap::from([(100, 
+            BatteryNotification { 
+                message: Some("Battery Fully Charged".to_string()), 
+                notification_icon: None, 
+                notification_sound: None, 
+                urgent_level: None,
+                inherited: NotificationDefaults::default(),
+            })]));
+
+        Self { notification_time: None, high_battery_levels, low_battery_levels: None, charger_notifications: None,
+            defaults: None, mute_sounds: None, minimum_urgency: None, profiles: None, active_profile: None,
+            profile_rules: None, announce_profile_switches: None, bar: None, include: None }
     }
 
     /// Getter function to return the time specified in the configuration file
@@ -55,41 +113,478 @@ impl Config {
         // default to 5000 ms (5 seconds)
         self.notification_time.unwrap_or(5000)
     }
+
+    /// Keeps only the sent thresholds that still exist in this config,
+    /// used when reloading so thresholds that already fired do not fire again
+    pub fn retain_sent_thresholds(&self, battery_notif_sent: &mut HashSet<u8>) {
+        battery_notif_sent.retain(|battery_level| {
+            [&self.low_battery_levels, &self.high_battery_levels]
+                .into_iter()
+                .flatten()
+                .any(|levels| levels.contains_key(battery_level))
+        });
+    }
+}
+
+// ----------------------------------------------------------------
+// Config Validation
+// ----------------------------------------------------------------
+#[derive(Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A semantic problem in a config that parsed successfully
+/// `key` is the dotted path to the offending key, e.g. `low_battery_levels.20.message`
+#[derive(Debug)]
+pub struct ConfigIssue {
+    pub severity: Severity,
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigIssue {
+    pub fn error(key: String, message: String) -> Self {
+        Self { severity: Severity::Error, key, message }
+    }
+
+    fn warning(key: String, message: String) -> Self {
+        Self { severity: Severity::Warning, key, message }
+    }
+}
+
+impl Config {
+    /// Checks the config and every profile for values that parse but can never work as intended
+    pub fn validate(&self) -> Vec<ConfigIssue> {
+        let mut issues = self.validate_notifications();
+
+        for (name, profile) in self.profiles.iter().flatten() {
+            let key = format!("profiles.{name}");
+            if name == DEFAULT_PROFILE {
+                issues.push(ConfigIssue::error(key.clone(),
+                    format!("the profile name \"{DEFAULT_PROFILE}\" is reserved")));
+            }
+            if profile.profiles.is_some() {
+                issues.push(ConfigIssue::error(format!("{key}.profiles"),
+                    "profiles cannot be nested".to_string()));
+                continue;
+            }
+            if profile.profile_rules.is_some() {
+                issues.push(ConfigIssue::error(format!("{key}.profile_rules"),
+                    "profile rules cannot be set in a profile".to_string()));
+            }
+            if profile.include.is_some() {
+                issues.push(ConfigIssue::error(format!("{key}.include"),
+                    "include cannot be set in a profile".to_string()));
+            }
+
+            // Only report what the profile breaks, the rest is already reported above
+            let mut with_profile = self.clone();
+            with_profile.apply_profile(name);
+            for issue in with_profile.validate_notifications() {
+                if !issues.iter().any(|existing| existing.key == issue.key && existing.message == issue.message) {
+                    issues.push(ConfigIssue { key: format!("{key}.{}", issue.key), ..issue });
+                }
+            }
+        }
+
+        for (index, rule) in self.profile_rules().iter().enumerate() {
+            let key = format!("profile_rules.{index}");
+            if rule.profile != DEFAULT_PROFILE && !self.profile_names().any(|name| name == rule.profile) {
+                issues.push(ConfigIssue::error(format!("{key}.profile"),
+                    format!("the profile \"{}\" does not exist", rule.profile)));
+            }
+            for invalid_condition in rule.invalid_conditions() {
+                issues.push(ConfigIssue::error(key.clone(), invalid_condition));
+            }
+        }
+        issues
+    }
+
+    fn validate_notifications(&self) -> Vec<ConfigIssue> {
+        let mut issues = self.bar().validate();
+
+        let sections = [
+            ("low_battery_levels", &self.low_battery_levels),
+            ("high_battery_levels", &self.high_battery_levels),
+        ];
+        for (section, levels) in sections {
+            for (battery_level, notification) in levels.iter().flatten() {
+                let key = format!("{section}.{battery_level}");
+                if *battery_level > 100 {
+                    issues.push(ConfigIssue::error(key.clone(),
+                        format!("threshold {battery_level} is above 100%")));
+                }
+                match notification.message.as_deref() {
+                    None => issues.push(ConfigIssue::error(format!("{key}.message"),
+                        "message is missing".to_string())),
+                    // Older versions of `--create-config` wrote an empty message, so it only warns
+                    Some(message) if message.trim().is_empty() => issues.push(ConfigIssue::warning(
+                        format!("{key}.message"), "message is empty, the notification has no text".to_string())),
+                    Some(_) => (),
+                }
+            }
+        }
+
+        // A low threshold at or above a high threshold means both fire around the same charge
+        let highest_low = self.low_battery_levels.iter().flat_map(|levels| levels.keys()).max();
+        let lowest_high = self.high_battery_levels.iter().flat_map(|levels| levels.keys()).min();
+        if let (Some(highest_low), Some(lowest_high)) = (highest_low, lowest_high)
+            && highest_low >= lowest_high {
+            issues.push(ConfigIssue::warning(format!("low_battery_levels.{highest_low}"),
+                format!("low threshold {highest_low} overlaps high threshold {lowest_high}")));
+        }
+
+        issues
+    }
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}: {}: {}", self.key, self.message)
+    }
+}
+
+impl Config {
+    /// Layers another config on top of this one field by field,
+    /// every field set in `overlay` replaces the same field in this config
+    /// An empty table in `overlay` disables the section or threshold, e.g. `low_battery_levels = {}`
+    pub fn layer(&mut self, overlay: Config) {
+        layer_field(&mut self.notification_time, overlay.notification_time);
+        layer_field(&mut self.mute_sounds, overlay.mute_sounds);
+        layer_field(&mut self.minimum_urgency, overlay.minimum_urgency);
+        layer_field(&mut self.profile_rules, overlay.profile_rules);
+        layer_field(&mut self.announce_profile_switches, overlay.announce_profile_switches);
+        layer_battery_levels(&mut self.high_battery_levels, overlay.high_battery_levels);
+        layer_battery_levels(&mut self.low_battery_levels, overlay.low_battery_levels);
+
+        match (self.charger_notifications.as_mut(), overlay.charger_notifications) {
+            (_, None) => (),
+            (_, Some(overlay)) if overlay.is_empty() => self.charger_notifications = None,
+            (Some(charger_notifications), Some(overlay)) => charger_notifications.layer(overlay),
+            (None, Some(overlay)) => self.charger_notifications = Some(overlay),
+        }
+
+        match (self.defaults.as_mut(), overlay.defaults) {
+            (_, None) => (),
+            (_, Some(overlay)) if overlay.is_empty() => self.defaults = None,
+            (Some(defaults), Some(overlay)) => defaults.layer(overlay),
+            (None, Some(overlay)) => self.defaults = Some(overlay),
+        }
+
+        match (self.bar.as_mut(), overlay.bar) {
+            (_, None) => (),
+            (_, Some(overlay)) if overlay.is_empty() => self.bar = None,
+            (Some(bar), Some(overlay)) => bar.layer(overlay),
+            (None, Some(overlay)) => self.bar = Some(overlay),
+        }
+
+        // Profiles with the same name are layered on top of each other
+        let Some(overlay_profiles) = overlay.profiles else { return };
+        let profiles = self.profiles.get_or_insert_with(BTreeMap::new);
+        for (name, profile) in overlay_profiles {
+            match profiles.get_mut(&name) {
+                Some(existing) => existing.layer(profile),
+                None => { profiles.insert(name, profile); },
+            }
+        }
+    }
+
+    /// Layers the named profile on top of this config
+    /// Returns false if the profile does not exist, the config is left unchanged in that case
+    pub fn apply_profile(&mut self, name: &str) -> bool {
+        let Some(profile) = self.profiles.as_ref().and_then(|profiles| profiles.get(name)).cloned() else {
+            return false;
+        };
+        self.layer(profile);
+        self.active_profile = Some(name.to_string());
+        true
+    }
+
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().flat_map(|profiles| profiles.keys()).map(String::as_str)
+    }
+
+    /// Checks a profile name before it is saved as the active profile, `default` is always allowed
+    pub fn check_profile_name(&self, name: &str) -> Result<(), String> {
+        if name == DEFAULT_PROFILE || self.profile(name).is_some() {
+            return Ok(());
+        }
+        let profile_names: Vec<&str> = self.profile_names().collect();
+        Err(format!("Could not find the profile \"{name}\", available profiles: {}", profile_names.join(", ")))
+    }
+
+    pub fn profile_rules(&self) -> &[ProfileRule] {
+        self.profile_rules.as_deref().unwrap_or_default()
+    }
+
+    pub fn announce_profile_switches(&self) -> bool {
+        self.announce_profile_switches.unwrap_or(false)
+    }
+
+    pub fn bar(&self) -> BarConfig {
+        self.bar.clone().unwrap_or_default()
+    }
+
+    /// Removes and returns the files listed in `include`, they are loaded by `collect_config_layers`
+    pub fn take_includes(&mut self) -> Vec<String> {
+        self.include.take().unwrap_or_default()
+    }
+
+    /// The profile as written in the config, without the rest of the config layered below it
+    pub fn profile(&self, name: &str) -> Option<&Config> {
+        self.profiles.as_ref()?.get(name)
+    }
+
+    /// Name of the active profile, None if no profile is active
+    pub fn active_profile(&self) -> Option<&str> {
+        self.active_profile.as_deref()
+    }
+
+    /// Applies `mute_sounds` and `minimum_urgency`,
+    /// must be called after `Config::resolve_inheritance` since both depend on inherited values
+    pub fn apply_sound_and_urgency_filters(&mut self) {
+        if self.mute_sounds.unwrap_or(false) {
+            for levels in [&mut self.low_battery_levels, &mut self.high_battery_levels].into_iter().flatten() {
+                levels.values_mut().for_each(|notification| notification.notification_sound = Some(String::new()));
+            }
+            if let Some(charger_notifications) = self.charger_notifications.as_mut() {
+                charger_notifications.plugged_sound = Some(String::new());
+                charger_notifications.unplugged_sound = Some(String::new());
+            }
+        }
+
+        if let Some(minimum_urgency) = self.minimum_urgency {
+            for levels in [&mut self.low_battery_levels, &mut self.high_battery_levels].into_iter().flatten() {
+                levels.retain(|_, notification| notification.urgent_level() >= minimum_urgency);
+            }
+            if self.charger_notifications.as_ref()
+                .is_some_and(|charger_notifications| charger_notifications.urgent_level() < minimum_urgency) {
+                self.charger_notifications = None;
+            }
+        }
+    }
+
+    /// Passes the `[defaults]`, `[defaults.low]` and `[defaults.high]` tables down to every notification
+    /// Must be called after layering, so the getters of each notification resolve through the inheritance chain
+    pub fn resolve_inheritance(&mut self) {
+        let Defaults { common, low, high } = self.defaults.clone().unwrap_or_default();
+
+        let mut low_defaults = common.clone();
+        low_defaults.layer(low.unwrap_or_default());
+        let mut high_defaults = common.clone();
+        high_defaults.layer(high.unwrap_or_default());
+
+        let sections = [
+            (&mut self.low_battery_levels, &low_defaults),
+            (&mut self.high_battery_levels, &high_defaults),
+        ];
+        for (levels, inherited) in sections {
+            levels.iter_mut()
+                .flat_map(|levels| levels.values_mut())
+                .for_each(|notification| notification.inherited = inherited.clone());
+        }
+        if let Some(charger_notifications) = self.charger_notifications.as_mut() {
+            charger_notifications.inherited = common;
+        }
+    }
+}
+
+fn layer_battery_levels(
+    levels: &mut Option<BTreeMap<u8, BatteryNotification>>,
+    overlay: Option<BTreeMap<u8, BatteryNotification>>
+) {
+    let Some(overlay) = overlay else { return };
+    if overlay.is_empty() {
+        *levels = Some(BTreeMap::new());
+        return;
+    }
+
+    let levels = levels.get_or_insert_with(BTreeMap::new);
+    for (battery_level, notification) in overlay {
+        if notification.is_empty() {
+            levels.remove(&battery_level);
+        } else if let Some(existing) = levels.get_mut(&battery_level) {
+            existing.layer(notification);
+        } else {
+            levels.insert(battery_level, notification);
+        }
+    }
+}
+
+/// Replaces `field` only if the overlay has a value
+pub fn layer_field<T>(field: &mut Option<T>, overlay: Option<T>) {
+    if overlay.is_some() {
+        *field = overlay;
+    }
+}
+
+impl ExpandPaths for Config {
+    fn expand_paths(&mut self, base_dir: &Path) {
+        for levels in [&mut self.low_battery_levels, &mut self.high_battery_levels].into_iter().flatten() {
+            levels.values_mut().for_each(|notification| notification.expand_paths(base_dir));
+        }
+        if let Some(charger_notifications) = self.charger_notifications.as_mut() {
+            charger_notifications.expand_paths(base_dir);
+        }
+        if let Some(defaults) = self.defaults.as_mut() {
+            defaults.expand_paths(base_dir);
+        }
+        for profile in self.profiles.iter_mut().flat_map(|profiles| profiles.values_mut()) {
+            profile.expand_paths(base_dir);
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+// Notification Defaults Structs and Implementation
+// ----------------------------------------------------------------
+/// Fields inherited by every notification that does not set them
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct NotificationDefaults {
+    notification_icon: Option<String>,
+    notification_sound: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_urgent_level")]
+    urgent_level: Option<UrgencyLevel>,
+}
+
+/// The `[defaults]` table, `[defaults.low]` and `[defaults.high]` only apply to
+/// low and high battery notifications and take priority over `[defaults]`
+#[derive(Debug, Default, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Defaults {
+    #[serde(flatten)]
+    common: NotificationDefaults,
+    low: Option<NotificationDefaults>,
+    high: Option<NotificationDefaults>,
+}
+
+impl NotificationDefaults {
+    fn is_empty(&self) -> bool {
+        self.notification_icon.is_none()
+            && self.notification_sound.is_none()
+            && self.urgent_level.is_none()
+    }
+
+    fn layer(&mut self, overlay: NotificationDefaults) {
+        layer_field(&mut self.notification_icon, overlay.notification_icon);
+        layer_field(&mut self.notification_sound, overlay.notification_sound);
+        layer_field(&mut self.urgent_level, overlay.urgent_level);
+    }
+}
+
+impl ExpandPaths for NotificationDefaults {
+    fn expand_paths(&mut self, base_dir: &Path) {
+        expand_optional(&mut self.notification_icon, base_dir, expand_icon);
+        expand_optional(&mut self.notification_sound, base_dir, expand_file_path);
+    }
+}
+
+impl Defaults {
+    fn is_empty(&self) -> bool {
+        self.common.is_empty() && self.low.is_none() && self.high.is_none()
+    }
+
+    fn layer(&mut self, overlay: Defaults) {
+        self.common.layer(overlay.common);
+        for (layer, overlay) in [(&mut self.low, overlay.low), (&mut self.high, overlay.high)] {
+            match (layer.as_mut(), overlay) {
+                (_, None) => (),
+                (Some(layer), Some(overlay)) => layer.layer(overlay),
+                (None, Some(overlay)) => *layer = Some(overlay),
+            }
+        }
+    }
+}
+
+impl ExpandPaths for Defaults {
+    fn expand_paths(&mut self, base_dir: &Path) {
+        self.common.expand_paths(base_dir);
+        for layer in [&mut self.low, &mut self.high].into_iter().flatten() {
+            layer.expand_paths(base_dir);
+        }
+    }
 }
 
 // ----------------------------------------------------------------
 // Battery Notification Struct and Implementation
 // ----------------------------------------------------------------
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct BatteryNotification{
-    message: String,
+    message: Option<String>,
     notification_icon: Option<String>,
     notification_sound: Option<String>,
-    urgent_level: Option<String>
+    #[serde(default, deserialize_with = "deserialize_urgent_level")]
+    urgent_level: Option<UrgencyLevel>,
+    /// Resolved from the `[defaults]` tables, see `Config::resolve_inheritance`
+    #[serde(skip)]
+    inherited: NotificationDefaults,
 }
 
 /// This implementation defines Getter functions for the fields in the above struct
 /// Also deals with option<> types by returning default values
 impl BatteryNotification {
     pub fn notification_message(&self) -> &str {
-        self.message.as_str()
+        self.message.as_deref().unwrap_or("")
     }
     
     pub fn notification_icon(&self) -> &str {
-        // Defaults to an empty string 
-        self.notification_icon.as_deref().unwrap_or("")
+        // Falls back to the inherited icon, then to an empty string
+        self.notification_icon.as_deref()
+            .or(self.inherited.notification_icon.as_deref())
+            .unwrap_or("")
     }
     
     pub fn notification_sound(&self) -> &str {
-        // Defaults to an empty string 
-        self.notification_sound.as_deref().unwrap_or("")
+        // Falls back to the inherited sound, then to an empty string
+        self.notification_sound.as_deref()
+            .or(self.inherited.notification_sound.as_deref())
+            .unwrap_or("")
+    }
+}
+
+impl BatteryNotification {
+    pub fn new(message: &str, notification_icon: Option<String>, notification_sound: Option<String>,
+        urgent_level: UrgencyLevel) -> Self {
+        BatteryNotification {
+            message: Some(message.to_string()),
+            notification_icon,
+            notification_sound,
+            urgent_level: Some(urgent_level),
+            inherited: NotificationDefaults::default(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.message.is_none()
+            && self.notification_icon.is_none()
+            && self.notification_sound.is_none()
+            && self.urgent_level.is_none()
+    }
+
+    fn layer(&mut self, overlay: BatteryNotification) {
+        layer_field(&mut self.message, overlay.message);
+        layer_field(&mut self.notification_icon, overlay.notification_icon);
+        layer_field(&mut self.notification_sound, overlay.notification_sound);
+        layer_field(&mut self.urgent_level, overlay.urgent_level);
+    }
+}
+
+impl ExpandPaths for BatteryNotification {
+    fn expand_paths(&mut self, base_dir: &Path) {
+        expand_optional(&mut self.notification_icon, base_dir, expand_icon);
+        expand_optional(&mut self.notification_sound, base_dir, expand_file_path);
     }
 }
 
 // ----------------------------------------------------------------
 // Charger Notification Struct and Implementation
 // ----------------------------------------------------------------
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct ChargerNotification {
     charging: Option<bool>,
     plugged_sound: Option<String>,
@@ -97,7 +592,11 @@ pub struct ChargerNotification {
     discharging: Option<bool>,
     unplugged_sound: Option<String>,
     discharging_icon: Option<String>,
-    urgent_level: Option<String>
+    #[serde(default, deserialize_with = "deserialize_urgent_level")]
+    urgent_level: Option<UrgencyLevel>,
+    /// Resolved from the `[defaults]` table, see `Config::resolve_inheritance`
+    #[serde(skip)]
+    inherited: NotificationDefaults,
 }
 
 /// This implementation defines Getter functions for the fields in the above struct
@@ -113,18 +612,141 @@ impl ChargerNotification {
     }
 
     pub fn icon_for_state(&self, state: &str) -> &str {
-        match state {
-            "Charging" => self.charging_icon.as_deref().unwrap_or(""),
-            "Discharging" => self.discharging_icon.as_deref().unwrap_or(""),
-            _ => ""
-        }
+        let icon = match state {
+            "Charging" => self.charging_icon.as_deref(),
+            "Discharging" => self.discharging_icon.as_deref(),
+            _ => return ""
+        };
+        icon.or(self.inherited.notification_icon.as_deref()).unwrap_or("")
     }
 
     pub fn sound_for_state(&self, state: &str) -> &str {
-         match state {
-            "Charging" => self.plugged_sound.as_deref().unwrap_or(""),
-            "Discharging" => self.unplugged_sound.as_deref().unwrap_or(""),
-            _ => ""
+        let sound = match state {
+            "Charging" => self.plugged_sound.as_deref(),
+            "Discharging" => self.unplugged_sound.as_deref(),
+            _ => return ""
+        };
+        sound.or(self.inherited.notification_sound.as_deref()).unwrap_or("")
+    }
+}
+
+impl ChargerNotification {
+    /// `plugged` and `unplugged` are the (icon, sound) to use, None disables that notification
+    pub fn new(plugged: Option<(Option<String>, Option<String>)>,
+        unplugged: Option<(Option<String>, Option<String>)>) -> Self {
+        let (charging_icon, plugged_sound) = plugged.clone().unwrap_or_default();
+        let (discharging_icon, unplugged_sound) = unplugged.clone().unwrap_or_default();
+        ChargerNotification {
+            charging: Some(plugged.is_some()),
+            plugged_sound,
+            charging_icon,
+            discharging: Some(unplugged.is_some()),
+            unplugged_sound,
+            discharging_icon,
+            urgent_level: Some(UrgencyLevel::Normal),
+            inherited: NotificationDefaults::default(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.charging.is_none()
+            && self.plugged_sound.is_none()
+            && self.charging_icon.is_none()
+            && self.discharging.is_none()
+            && self.unplugged_sound.is_none()
+            && self.discharging_icon.is_none()
+            && self.urgent_level.is_none()
+    }
+
+    fn layer(&mut self, overlay: ChargerNotification) {
+        layer_field(&mut self.charging, overlay.charging);
+        layer_field(&mut self.plugged_sound, overlay.plugged_sound);
+        layer_field(&mut self.charging_icon, overlay.charging_icon);
+        layer_field(&mut self.discharging, overlay.discharging);
+        layer_field(&mut self.unplugged_sound, overlay.unplugged_sound);
+        layer_field(&mut self.discharging_icon, overlay.discharging_icon);
+        layer_field(&mut self.urgent_level, overlay.urgent_level);
+    }
+}
+
+impl ExpandPaths for ChargerNotification {
+    fn expand_paths(&mut self, base_dir: &Path) {
+        expand_optional(&mut self.charging_icon, base_dir, expand_icon);
+        expand_optional(&mut self.discharging_icon, base_dir, expand_icon);
+        expand_optional(&mut self.plugged_sound, base_dir, expand_file_path);
+        expand_optional(&mut self.unplugged_sound, base_dir, expand_file_path);
+    }
+}
+
+// ----------------------------------------------------------------
+// Urgency Level Enum
+// ----------------------------------------------------------------
+/// Urgency of a notification, accepted in any case in the config file
+/// e.g. "critical", "Critical" and "CRITICAL" are all valid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum UrgencyLevel {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl UrgencyLevel {
+    pub const VARIANTS: &'static [&'static str] = &["Low", "Normal", "Critical"];
+}
+
+impl<'de> Deserialize<'de> for UrgencyLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        match value.trim().to_lowercase().as_str() {
+            "low" => Ok(UrgencyLevel::Low),
+            "normal" => Ok(UrgencyLevel::Normal),
+            "critical" => Ok(UrgencyLevel::Critical),
+            _ => Err(serde::de::Error::unknown_variant(&value, Self::VARIANTS)),
+        }
+    }
+}
+
+/// An `enum` in the schema would only allow the exact variant names, so it is a case-insensitive pattern instead
+/// JSON Schema patterns have no flag for ignoring case, every letter is a class like `[Ll]`
+impl JsonSchema for UrgencyLevel {
+    fn schema_name() -> Cow<'static, str> {
+        "UrgencyLevel".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        let variants: Vec<String> = Self::VARIANTS
+            .iter()
+            .map(|variant| variant.chars().map(|letter| format!("[{}{}]", letter.to_uppercase(), letter.to_lowercase())).collect())
+            .collect();
+        json_schema!({
+            "description": "Urgency of a notification, accepted in any case in the config file",
+            "type": "string",
+            "pattern": format!("^\\s*({})\\s*$", variants.join("|")),
+            "examples": Self::VARIANTS,
+        })
+    }
+}
+
+/// Configs written by older versions of `--create-config` have `urgent_level = ""` in every notification,
+/// it is read as unset so those configs keep loading instead of falling back to the defaults
+fn deserialize_urgent_level<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<UrgencyLevel>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(value) if value.trim().is_empty() => {
+            static DEPRECATION_WARNING: Once = Once::new();
+            DEPRECATION_WARNING.call_once(|| warn!("urgent_level = \"\" is deprecated and read as unset, remove it or use one of {}", UrgencyLevel::VARIANTS.join(", ")));
+            Ok(None)
+        },
+        Some(value) => UrgencyLevel::deserialize(value.into_deserializer()).map(Some),
+    }
+}
+
+impl From<UrgencyLevel> for Urgency {
+    fn from(urgent_level: UrgencyLevel) -> Self {
+        match urgent_level {
+            UrgencyLevel::Low => Urgency::Low,
+            UrgencyLevel::Normal => Urgency::Normal,
+            UrgencyLevel::Critical => Urgency::Critical,
         }
     }
 }
@@ -133,27 +755,21 @@ impl ChargerNotification {
 // Trait for Battery Notification and Charger Notification 
 // ----------------------------------------------------------------
 pub trait GetUrgency {
-    fn urgent_level(&self) -> &str;
+    fn urgent_level(&self) -> UrgencyLevel;
 
     fn urgency(&self) -> Urgency {
-        match self.urgent_level().try_into(){
-            Ok(urgency) => {return urgency;},
-            Err(_) => {
-                eprintln!("Unsupported urgency level, defaulting to Normal");
-            }
-        };
-        Urgency::Normal
+        self.urgent_level().into()
     }
 }
 
 impl GetUrgency for BatteryNotification {
-    fn urgent_level(&self) -> &str {
-        self.urgent_level.as_deref().unwrap_or("Normal").trim()
+    fn urgent_level(&self) -> UrgencyLevel {
+        self.urgent_level.or(self.inherited.urgent_level).unwrap_or(UrgencyLevel::Normal)
     }
 }
 
 impl GetUrgency for ChargerNotification {
-    fn urgent_level(&self) -> &str {
-        self.urgent_level.as_deref().unwrap_or("Normal").trim()
+    fn urgent_level(&self) -> UrgencyLevel {
+        self.urgent_level.or(self.inherited.urgent_level).unwrap_or(UrgencyLevel::Normal)
     }
-}
\ No newline at end of file
+}
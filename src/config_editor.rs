@@ -0,0 +1,374 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+
+use toml_edit::{Decor, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::config::Config;
+use crate::config_file_manager::{validate_layered_config, ConfigError, ConfigFormat};
+
+//  ----------------------------------------------------
+//  Config Editing, used by `spider-volt config get/set/unset`
+//  ----------------------------------------------------
+#[derive(Debug)]
+pub enum EditError {
+    /// Only toml files can be edited without losing comments
+    UnsupportedFormat(String),
+    Read(String, io::Error),
+    Parse(String, String),
+    /// The key, and what is wrong with it
+    Key(String, String),
+    /// The edit would make the config invalid, it was not saved
+    Invalid(ConfigError),
+    Write(String, io::Error),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::UnsupportedFormat(path) => write!(f, "Only toml config files can be edited, {path} is not a toml file"),
+            EditError::Read(path, error) => write!(f, "Could not read {path}: {error}"),
+            EditError::Parse(path, error) => write!(f, "Failed to parse {path}\n{error}"),
+            EditError::Key(key, error) => write!(f, "{key} {error}"),
+            EditError::Invalid(error) => write!(f, "{error}\nThe config file was not changed"),
+            EditError::Write(path, error) => write!(f, "Could not write {path}: {error}"),
+        }
+    }
+}
+
+/// Returns the value of a dotted key in the config file,
+/// strings are returned without quotes and tables as toml
+pub fn config_get(file_path: &str, key: &str) -> Result<String, EditError> {
+    let document = read_document(file_path, false)?;
+    let segments = split_key(key)?;
+
+    let (last_segment, parent_segments) = segments.split_last().unwrap_or((&"", &[]));
+    let table = find_table(document.as_table(), parent_segments)
+        .ok_or_else(|| EditError::Key(key.to_string(), format!("is not set in {file_path}")))?;
+    let item = table.get(last_segment)
+        .ok_or_else(|| EditError::Key(key.to_string(), format!("is not set in {file_path}")))?;
+
+    Ok(match item {
+        Item::Value(Value::String(string)) => string.value().to_string(),
+        Item::Value(value) => value.clone().decorated("", "").to_string(),
+        Item::Table(table) => table.to_string().trim_end().to_string(),
+        Item::ArrayOfTables(array) => array.to_string().trim_end().to_string(),
+        Item::None => String::new(),
+    })
+}
+
+/// Sets a dotted key in the config file, creating the file and any missing tables
+/// The value is parsed as toml, anything that is not valid toml is stored as a string
+pub fn config_set(file_path: &str, key: &str, raw_value: &str) -> Result<(), EditError> {
+    let mut document = read_document(file_path, true)?;
+    let segments = split_key(key)?;
+    let mut value = parse_value(raw_value);
+
+    let (last_segment, parent_segments) = segments.split_last().unwrap_or((&"", &[]));
+    let table = find_or_create_table(document.as_table_mut(), parent_segments)
+        .map_err(|error| EditError::Key(key.to_string(), error))?;
+
+    // Replacing the item in place keeps the comments above the key and after the old value
+    match table.get_mut(last_segment) {
+        Some(item) => {
+            if let Item::Value(old_value) = item {
+                *value.decor_mut() = old_value.decor().clone();
+            }
+            *item = Item::Value(value);
+        },
+        None => {
+            // The space before the `}` of an inline table belongs to its last value, so it moves to the new last value
+            if let Some((_, Item::Value(last_value))) = table.iter_mut().last()
+                && let Some(suffix) = last_value.decor().suffix().and_then(|suffix| suffix.as_str())
+                && !suffix.is_empty() && suffix.trim().is_empty() {
+                value.decor_mut().set_suffix(suffix.to_string());
+                last_value.decor_mut().set_suffix("");
+            }
+            table.insert(last_segment, Item::Value(value));
+        },
+    }
+    save_document(file_path, &document)
+}
+
+/// Removes a dotted key from the config file,
+/// tables left empty by the removal are removed too, since an empty table disables the defaults
+pub fn config_unset(file_path: &str, key: &str) -> Result<(), EditError> {
+    let mut document = read_document(file_path, false)?;
+    let segments = split_key(key)?;
+    let mut orphaned_comments = Vec::new();
+    if !remove_key(document.as_table_mut(), None, &segments, &mut orphaned_comments) {
+        return Err(EditError::Key(key.to_string(), format!("is not set in {file_path}")));
+    }
+    for orphaned_comment in orphaned_comments {
+        orphaned_comment.attach(&mut document);
+    }
+    save_document(file_path, &document)
+}
+
+fn split_key(key: &str) -> Result<Vec<&str>, EditError> {
+    let segments: Vec<&str> = key.split('.').map(str::trim).collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(EditError::Key(key.to_string(), "expected a dotted key, e.g. low_battery_levels.20.message".to_string()));
+    }
+    Ok(segments)
+}
+
+pub fn parse_value(raw_value: &str) -> Value {
+    raw_value.parse::<Value>().unwrap_or_else(|_| Value::from(raw_value))
+}
+
+/// A missing file is only an error when reading, `config set` creates it
+fn read_document(file_path: &str, allow_missing: bool) -> Result<DocumentMut, EditError> {
+    if ConfigFormat::from_path(Path::new(file_path)) != ConfigFormat::Toml {
+        return Err(EditError::UnsupportedFormat(file_path.to_string()));
+    }
+    let source = match fs::read_to_string(file_path) {
+        Ok(source) => source,
+        Err(error) if allow_missing && error.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(error) => return Err(EditError::Read(file_path.to_string(), error)),
+    };
+    source.parse::<DocumentMut>()
+        .map_err(|error| EditError::Parse(file_path.to_string(), error.to_string()))
+}
+
+/// Validates the edited document the same way `--check-config` does, then replaces the file
+/// The file is replaced by a rename, which a running daemon sees as a change and reloads
+fn save_document(file_path: &str, document: &DocumentMut) -> Result<(), EditError> {
+    let config_str = document.to_string();
+    let file_config = ConfigFormat::Toml.parse(&config_str)
+        .map_err(|error| EditError::Invalid(ConfigError::Parse(file_path.to_string(), error)))?;
+    let mut config = Config::default();
+    config.layer(file_config);
+    validate_layered_config(config, file_path).map_err(EditError::Invalid)?;
+
+    let write_error = |error| EditError::Write(file_path.to_string(), error);
+    // A symlinked config, e.g. into a dotfiles repo, is edited where it points to instead of being replaced
+    let path = fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+    if let Some(config_dir) = path.parent() {
+        fs::create_dir_all(config_dir).map_err(write_error)?;
+    }
+    let mut temporary_path = path.clone().into_os_string();
+    temporary_path.push(".tmp");
+    fs::write(&temporary_path, config_str).map_err(write_error)?;
+    // The new file keeps the permissions of the one it replaces
+    if let Ok(metadata) = fs::metadata(&path) {
+        fs::set_permissions(&temporary_path, metadata.permissions()).map_err(write_error)?;
+    }
+    fs::rename(&temporary_path, &path).map_err(write_error)
+}
+
+/// Follows the segments through tables, inline tables and arrays of tables, e.g. `profile_rules.0`
+fn find_table<'a>(table: &'a dyn TableLike, segments: &[&str]) -> Option<&'a dyn TableLike> {
+    let Some((segment, rest)) = segments.split_first() else { return Some(table) };
+    let inner_table: &dyn TableLike = match table.get(segment)? {
+        Item::ArrayOfTables(array) => array.get(segment_index(rest.first()?)?)?,
+        Item::Value(Value::Array(array)) => array.get(segment_index(rest.first()?)?)?.as_inline_table()?,
+        item => return find_table(item.as_table_like()?, rest),
+    };
+    find_table(inner_table, &rest[1..])
+}
+
+pub fn find_or_create_table<'a>(table: &'a mut dyn TableLike, segments: &[&str]) -> Result<&'a mut dyn TableLike, String> {
+    find_or_create_nested_table(table, segments, false)
+}
+
+/// Follows the segments like `find_table`, creating the tables that are missing
+/// `inline` is set inside inline tables, where only inline tables can be created
+fn find_or_create_nested_table<'a>(table: &'a mut dyn TableLike, segments: &[&str], inline: bool)
+    -> Result<&'a mut dyn TableLike, String> {
+    let Some((segment, rest)) = segments.split_first() else { return Ok(table) };
+    if !table.contains_key(segment) {
+        let new_table = if inline {
+            Item::Value(Value::InlineTable(InlineTable::new()))
+        } else {
+            // Implicit tables are not written as their own header, e.g. only [low_battery_levels.15]
+            let mut new_table = Table::new();
+            new_table.set_implicit(true);
+            Item::Table(new_table)
+        };
+        table.insert(segment, new_table);
+    }
+
+    let item = table.get_mut(segment).ok_or_else(|| format!("{segment} could not be created"))?;
+    let list_index = || rest.first().and_then(|index| segment_index(index))
+        .ok_or_else(|| format!("{segment} is a list, expected an index after it"));
+    let (inner_table, rest, inline): (&mut dyn TableLike, &[&str], bool) = match item {
+        Item::ArrayOfTables(array) => {
+            let index = list_index()?;
+            let table = array.get_mut(index).ok_or_else(|| format!("{segment} has no entry {index}"))?;
+            (table, &rest[1..], false)
+        },
+        // A list of inline tables, e.g. `profile_rules = [{ profile = "desk" }]`
+        Item::Value(Value::Array(array)) => {
+            let index = list_index()?;
+            let table = array.get_mut(index)
+                .ok_or_else(|| format!("{segment} has no entry {index}"))?
+                .as_inline_table_mut()
+                .ok_or_else(|| format!("{segment}.{index} is not a table"))?;
+            (table, &rest[1..], true)
+        },
+        item => {
+            let inline = item.is_value();
+            (item.as_table_like_mut().ok_or_else(|| format!("{segment} is not a table"))?, rest, inline)
+        },
+    };
+    find_or_create_nested_table(inner_table, rest, inline)
+}
+
+/// Comments in front of a removed key or table, toml_edit stores them with the removed item,
+/// they are moved to the next table so comments about other parts of the file are kept
+struct OrphanedComment {
+    comment: String,
+    /// Position of the table the comment was in, None for the root table
+    after_position: Option<isize>,
+}
+
+impl OrphanedComment {
+    fn new(decor: &Decor, after_position: Option<isize>) -> Option<Self> {
+        let comment = decor.prefix()?.as_str()?;
+        comment.contains('#').then(|| OrphanedComment { comment: comment.to_string(), after_position })
+    }
+
+    fn attach(self, document: &mut DocumentMut) {
+        let next_position = table_positions(document.as_table())
+            .into_iter()
+            .filter(|position| self.after_position.is_none_or(|after_position| *position > after_position))
+            .min();
+        let next_table = next_position.and_then(|position| table_at_position(document.as_table_mut(), position));
+        match next_table {
+            Some(table) => {
+                let prefix = table.decor().prefix().and_then(|prefix| prefix.as_str()).unwrap_or("").to_string();
+                table.decor_mut().set_prefix(format!("{}{prefix}", self.comment));
+            },
+            None => {
+                let trailing = document.trailing().as_str().unwrap_or("").to_string();
+                document.set_trailing(format!("{trailing}{}", self.comment));
+            },
+        }
+    }
+}
+
+/// Returns true if the key was found and removed
+fn remove_key(table: &mut dyn TableLike, table_position: Option<isize>, segments: &[&str],
+    orphaned_comments: &mut Vec<OrphanedComment>) -> bool {
+    let Some((segment, rest)) = segments.split_first() else { return false };
+    if rest.is_empty() {
+        return remove_item(table, table_position, segment, orphaned_comments);
+    }
+
+    let Some(item) = table.get_mut(segment) else { return false };
+    let removed = match item {
+        Item::ArrayOfTables(array) => match rest.first().and_then(|index| segment_index(index)) {
+            Some(index) if rest.len() == 1 && index < array.len() => {
+                let removed_table = array.get(index).map(|table| (table.decor().clone(), table.position()));
+                if let Some((decor, position)) = removed_table {
+                    orphaned_comments.extend(OrphanedComment::new(&decor, position));
+                }
+                array.remove(index);
+                true
+            },
+            Some(index) => array.get_mut(index).is_some_and(|table| {
+                let position = table.position();
+                remove_key(table, position, &rest[1..], orphaned_comments)
+            }),
+            None => false,
+        },
+        Item::Table(inner_table) => {
+            let position = inner_table.position();
+            remove_key(inner_table, position, rest, orphaned_comments)
+        },
+        item => item.as_table_like_mut()
+            .is_some_and(|inner_table| remove_key(inner_table, table_position, rest, orphaned_comments)),
+    };
+
+    if removed && table.get(segment).is_some_and(is_empty_table) {
+        remove_item(table, table_position, segment, orphaned_comments);
+    }
+    removed
+}
+
+/// Removes a key from the table, keeping the comments in front of it
+fn remove_item(table: &mut dyn TableLike, table_position: Option<isize>, key: &str,
+    orphaned_comments: &mut Vec<OrphanedComment>) -> bool {
+    let key_decor = table.key(key).map(|key| key.leaf_decor().clone());
+    // The comments in front of a value move to the next value in the same table
+    let next_value_key = table.iter()
+        .skip_while(|(table_key, _)| *table_key != key)
+        .skip(1)
+        .find(|(_, item)| item.is_value())
+        .map(|(table_key, _)| table_key.to_string());
+
+    let Some(item) = table.remove(key) else { return false };
+    match item {
+        Item::Value(_) => {
+            let Some(orphaned_comment) = key_decor.and_then(|decor| OrphanedComment::new(&decor, table_position)) else {
+                return true;
+            };
+            match next_value_key.and_then(|next_value_key| table.key_mut(&next_value_key)) {
+                Some(mut next_key) => {
+                    let prefix = next_key.leaf_decor().prefix().and_then(|prefix| prefix.as_str()).unwrap_or("").to_string();
+                    next_key.leaf_decor_mut().set_prefix(format!("{}{prefix}", orphaned_comment.comment));
+                },
+                None => orphaned_comments.push(orphaned_comment),
+            }
+        },
+        Item::Table(removed_table) => {
+            orphaned_comments.extend(OrphanedComment::new(removed_table.decor(), removed_table.position()));
+        },
+        Item::ArrayOfTables(removed_tables) => {
+            orphaned_comments.extend(removed_tables.iter()
+                .filter_map(|removed_table| OrphanedComment::new(removed_table.decor(), removed_table.position())));
+        },
+        Item::None => (),
+    }
+    true
+}
+
+/// Positions of every table in the document, in no particular order
+fn table_positions(table: &Table) -> Vec<isize> {
+    let mut positions = Vec::new();
+    for (_, item) in table.iter() {
+        match item {
+            Item::Table(inner_table) => {
+                positions.extend(inner_table.position());
+                positions.extend(table_positions(inner_table));
+            },
+            Item::ArrayOfTables(array) => {
+                for inner_table in array.iter() {
+                    positions.extend(inner_table.position());
+                    positions.extend(table_positions(inner_table));
+                }
+            },
+            _ => (),
+        }
+    }
+    positions
+}
+
+fn table_at_position(table: &mut Table, position: isize) -> Option<&mut Table> {
+    for (_, item) in table.iter_mut() {
+        let inner_tables: Vec<&mut Table> = match item {
+            Item::Table(inner_table) => vec![inner_table],
+            Item::ArrayOfTables(array) => array.iter_mut().collect(),
+            _ => continue,
+        };
+        for inner_table in inner_tables {
+            if inner_table.position() == Some(position) {
+                return Some(inner_table);
+            }
+            if let Some(found_table) = table_at_position(inner_table, position) {
+                return Some(found_table);
+            }
+        }
+    }
+    None
+}
+
+fn is_empty_table(item: &Item) -> bool {
+    match item {
+        Item::ArrayOfTables(array) => array.is_empty(),
+        item => item.as_table_like().is_some_and(|table| table.is_empty()),
+    }
+}
+
+fn segment_index(segment: &str) -> Option<usize> {
+    segment.parse().ok()
+}
@@ -1,74 +1,548 @@
-use std::{env, fs, path::Path};
-use crate::config::Config;
+use std::{collections::BTreeMap, env, fmt, fs, io::{self, Write}, path::{Path, PathBuf}};
+use log::{error, info, warn};
+use schemars::Schema;
+use toml::de::{DeTable, DeValue};
 
-pub fn get_config_dir() -> String {
-    let home_directory = match env::home_dir() {
-        Some(home_directory) =>  home_directory,
-        None => {
-            eprintln!("Could not get home dir!");
-            return "".to_string();
+use crate::config::{Config, ConfigIssue, Severity};
+use crate::config_origins::config_origins;
+use crate::config_template::config_template;
+use crate::path_expansion::{expand_file_path, ExpandPaths};
+
+const APP_NAME: &str = "spider-volt";
+/// Looked up in this order, the first one that exists is used
+const CONFIG_FILE_NAMES: [&str; 4] = ["config.toml", "config.yaml", "config.yml", "config.json"];
+/// Extensions of the files loaded from the drop-in directory
+const CONFIG_EXTENSIONS: [&str; 4] = ["toml", "yaml", "yml", "json"];
+/// Directory next to a config file, every config file in it is layered on top of that config file
+const DROP_IN_DIR_NAME: &str = "config.d";
+
+/// Path to the user's config file, `$XDG_CONFIG_HOME/spider-volt/config.toml`
+/// falls back to `~/.config/spider-volt/config.toml` if `$XDG_CONFIG_HOME` is unset
+/// `config.yaml`, `config.yml` or `config.json` are used instead if they exist and `config.toml` does not
+pub fn user_config_path() -> String {
+    let config_home = match xdg_dir_from_env("XDG_CONFIG_HOME") {
+        Some(config_home) => config_home,
+        None => match env::home_dir() {
+            Some(home_directory) => home_directory.join(".config"),
+            None => {
+                error!("Could not get home dir!");
+                return "".to_string();
+            }
+        },
+    };
+    config_file_in(&config_home.join(APP_NAME)).display().to_string()
+}
+
+/// System wide config files, from the lowest to the highest priority
+/// `/etc/spider-volt/config.toml` and then every `$XDG_CONFIG_DIRS/spider-volt/config.toml`
+pub fn system_config_paths() -> Vec<PathBuf> {
+    system_config_paths_in(env::var("XDG_CONFIG_DIRS").ok())
+}
+
+/// `config_dirs` is the value of `$XDG_CONFIG_DIRS`, a colon separated list of directories
+pub fn system_config_paths_in(config_dirs: Option<String>) -> Vec<PathBuf> {
+    let config_dirs = config_dirs
+        .filter(|config_dirs| !config_dirs.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".to_string());
+
+    let mut paths = vec![config_file_in(&Path::new("/etc").join(APP_NAME))];
+    // The first directory in $XDG_CONFIG_DIRS is the most important one, so it is layered last
+    paths.extend(
+        config_dirs
+            .rsplit(':')
+            .map(Path::new)
+            // relative paths are invalid according to the XDG spec and are ignored
+            .filter(|config_dir| config_dir.is_absolute())
+            .map(|config_dir| config_file_in(&config_dir.join(APP_NAME))),
+    );
+    paths
+}
+
+/// Every config file to load, from the lowest to the highest priority
+/// the user's config file is layered on top of the system config files
+pub fn config_file_chain(user_config_path: &str) -> Vec<PathBuf> {
+    let mut config_files = system_config_paths();
+    config_files.push(PathBuf::from(user_config_path));
+    config_files
+}
+
+/// The first config file that exists in `config_dir`, `config.toml` if none exist
+fn config_file_in(config_dir: &Path) -> PathBuf {
+    CONFIG_FILE_NAMES
+        .iter()
+        .map(|file_name| config_dir.join(file_name))
+        .find(|config_file| config_file.exists())
+        .unwrap_or_else(|| config_dir.join(CONFIG_FILE_NAMES[0]))
+}
+
+/// XDG variables are only used if they are set to an absolute path
+pub fn xdg_dir_from_env(variable: &str) -> Option<PathBuf> {
+    env::var_os(variable)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+// ----------------------------------------------------------------
+// Config Errors
+// ----------------------------------------------------------------
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, io::Error),
+    Parse(String, String),
+    Invalid(String, Vec<ConfigIssue>),
+    Include(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, error) => write!(f, "Could not read {path}: {error}"),
+            // parse errors of every format already include the line and column
+            ConfigError::Parse(path, error) => write!(f, "Failed to parse {path}\n{error}"),
+            ConfigError::Invalid(path, issues) => {
+                write!(f, "Invalid config {path}")?;
+                issues.iter().try_for_each(|issue| write!(f, "\n{issue}"))
+            }
+            ConfigError::Include(path, error) => write!(f, "Could not include {path}: {error}"),
         }
+    }
+}
+
+// ----------------------------------------------------------------
+// Config File Formats
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub const NAMES: [&'static str; 3] = ["toml", "json", "yaml"];
+
+    /// Detects the format from the file extension, defaults to toml
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => ConfigFormat::Json,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Toml,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "toml" => Some(ConfigFormat::Toml),
+            "json" => Some(ConfigFormat::Json),
+            "yaml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, source: &str) -> Result<Config, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(source).map_err(|error| error.to_string()),
+            ConfigFormat::Json => serde_json::from_str(source).map_err(|error| error.to_string()),
+            ConfigFormat::Yaml => serde_yaml_ng::from_str(source).map_err(|error| error.to_string()),
+        }
+    }
+
+    pub fn serialize(self, config: &Config) -> Result<String, String> {
+        match self {
+            ConfigFormat::Toml => toml::to_string_pretty(config).map_err(|error| error.to_string()),
+            ConfigFormat::Json => {
+                let mut value = serde_json::to_value(config).map_err(|error| error.to_string())?;
+                remove_json_nulls(&mut value);
+                serde_json::to_string_pretty(&value).map_err(|error| error.to_string())
+            },
+            ConfigFormat::Yaml => {
+                let mut value = serde_yaml_ng::to_value(config).map_err(|error| error.to_string())?;
+                remove_yaml_nulls(&mut value);
+                serde_yaml_ng::to_string(&value).map_err(|error| error.to_string())
+            },
+        }
+    }
+}
+
+// Unset fields are serialized as null, toml skips them so json and yaml should too
+fn remove_json_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_json_nulls);
+        },
+        serde_json::Value::Array(values) => values.iter_mut().for_each(remove_json_nulls),
+        _ => (),
+    }
+}
+
+fn remove_yaml_nulls(value: &mut serde_yaml_ng::Value) {
+    match value {
+        serde_yaml_ng::Value::Mapping(mapping) => {
+            mapping.retain(|_, value| !value.is_null());
+            mapping.values_mut().for_each(remove_yaml_nulls);
+        },
+        serde_yaml_ng::Value::Sequence(values) => values.iter_mut().for_each(remove_yaml_nulls),
+        _ => (),
+    }
+}
+
+/// Parses a config file, the format is detected from the file extension
+pub fn parse_config_file(path: &Path) -> Result<Config, ConfigError> {
+    let file = fs::read_to_string(path)
+        .map_err(|error| ConfigError::Read(path.display().to_string(), error))?;
+    ConfigFormat::from_path(path)
+        .parse(&file)
+        .map_err(|error| ConfigError::Parse(path.display().to_string(), error))
+}
+
+/// The message goes to stderr so the config itself can be piped into a file
+pub fn print_config_with_message(config: &Config, message: &str, format: ConfigFormat) -> Result<(), String> {
+    info!("{message}\n");
+    let config_str = format.serialize(config)?;
+    println!("{config_str}");
+    Ok(())
+}
+
+/// Prints a JSON Schema of the config, for editor autocompletion and validation
+pub fn config_schema() -> Schema {
+    schemars::schema_for!(Config)
+}
+
+pub fn print_config_schema() -> Result<(), serde_json::Error> {
+    println!("{}", serde_json::to_string_pretty(&config_schema())?);
+    Ok(())
+}
+
+pub fn handle_config_file(config_files: &[PathBuf], overrides: Option<&Config>, profile: Option<&str>) -> Config {
+    load_config_chain(config_files, overrides, profile).unwrap_or_else(|error| {
+        error!("{error}\nUsing defaults");
+        Config::default()
+    })
+}
+
+/// Layers every existing config file in the chain on top of the default config,
+/// then layers the profile on top of that if one is given
+/// The overrides from the environment and command line are layered on top of everything
+/// The layered config is validated as a whole, since a single file can be partial
+pub fn load_config_chain(config_files: &[PathBuf], overrides: Option<&Config>, profile: Option<&str>) -> Result<Config, ConfigError> {
+    let mut layered_config = Config::default();
+
+    let layers = collect_config_layers(config_files)?;
+    if layers.is_empty() {
+        info!("Could not find config.toml, Using Defaults");
+    }
+    for layer in layers {
+        layered_config.layer(layer.config);
+    }
+
+    if let Some(overrides) = overrides {
+        layered_config.layer(overrides.clone());
+    }
+
+    let mut config = validate_layered_config(layered_config, "the layered config")?;
+    if let Some(profile) = profile
+        && !config.apply_profile(profile) {
+        warn!("Could not find the profile \"{profile}\", Using no profile");
+    }
+    // Layered again so the overrides also take priority over the profile, layering twice changes nothing else
+    if let Some(overrides) = overrides
+        && config.active_profile().is_some() {
+        config.layer(overrides.clone());
+    }
+    config.resolve_inheritance();
+    config.apply_sound_and_urgency_filters();
+    Ok(config)
+}
+
+// ----------------------------------------------------------------
+// Includes and Drop-ins
+// ----------------------------------------------------------------
+/// A config file and the config parsed from it
+pub struct ConfigLayer {
+    pub path: PathBuf,
+    pub config: Config,
+}
+
+/// Loads every config file in the chain, in the order they are layered
+/// Each file comes after the files it includes, and before the drop-ins in the `config.d` directory next to it
+pub fn collect_config_layers(config_files: &[PathBuf]) -> Result<Vec<ConfigLayer>, ConfigError> {
+    let mut layers = Vec::new();
+    for config_file in config_files {
+        if config_file.exists() {
+            load_with_includes(config_file, &mut Vec::new(), &mut layers)?;
+        }
+        for drop_in_file in drop_in_files(config_file) {
+            load_with_includes(&drop_in_file, &mut Vec::new(), &mut layers)?;
+        }
+    }
+    Ok(layers)
+}
+
+/// `including_files` are the files that led to this one, used to detect include cycles
+fn load_with_includes(path: &Path, including_files: &mut Vec<PathBuf>, layers: &mut Vec<ConfigLayer>)
+    -> Result<(), ConfigError> {
+    let absolute_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if including_files.contains(&absolute_path) {
+        return Err(ConfigError::Include(path.display().to_string(), "the file includes itself".to_string()));
+    }
+    let mut config = load_config_file(path)?;
+
+    let base_dir = absolute_path.parent().unwrap_or(Path::new("."));
+    including_files.push(absolute_path.clone());
+    for include in config.take_includes() {
+        let include_path = PathBuf::from(expand_file_path(&include, base_dir));
+        if !include_path.exists() {
+            return Err(ConfigError::Include(include_path.display().to_string(),
+                format!("the file does not exist, it is included from {}", path.display())));
+        }
+        load_with_includes(&include_path, including_files, layers)?;
+    }
+    including_files.pop();
+
+    layers.push(ConfigLayer { path: path.to_path_buf(), config });
+    Ok(())
+}
+
+/// The `config.d` directory next to a config file
+pub fn drop_in_dir(config_file: &Path) -> Option<PathBuf> {
+    Some(config_file.parent()?.join(DROP_IN_DIR_NAME))
+}
+
+/// Config files in the drop-in directory, in lexical order
+/// Only files with a config extension are used, so editor backups are skipped
+fn drop_in_files(config_file: &Path) -> Vec<PathBuf> {
+    let Some(Ok(entries)) = drop_in_dir(config_file).map(fs::read_dir) else { return Vec::new() };
+    let mut drop_in_files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| CONFIG_EXTENSIONS.contains(&extension)))
+        .collect();
+    drop_in_files.sort();
+    drop_in_files
+}
+
+/// Everything a running daemon should watch to notice config changes
+pub struct ConfigSources {
+    /// Config files, included files, and the drop-in directories themselves so their creation is noticed
+    pub files: Vec<PathBuf>,
+    /// Drop-in directories, any file created or changed in them is a change
+    pub directories: Vec<PathBuf>,
+}
+
+/// Files that fail to load are still watched, so fixing them triggers a reload
+pub fn config_sources(config_files: &[PathBuf]) -> ConfigSources {
+    let mut files = config_files.to_vec();
+    let directories: Vec<PathBuf> = config_files.iter().filter_map(|config_file| drop_in_dir(config_file)).collect();
+    files.extend(directories.iter().cloned());
+
+    let mut pending_files: Vec<PathBuf> = config_files.iter()
+        .cloned()
+        .chain(config_files.iter().flat_map(|config_file| drop_in_files(config_file)))
+        .collect();
+    while let Some(config_file) = pending_files.pop() {
+        let Ok(mut config) = parse_config_file(&config_file) else { continue };
+        let absolute_path = fs::canonicalize(&config_file).unwrap_or(config_file);
+        let base_dir = absolute_path.parent().unwrap_or(Path::new("."));
+        for include in config.take_includes() {
+            let include_path = PathBuf::from(expand_file_path(&include, base_dir));
+            // Already known files are skipped, which also stops include cycles
+            if !files.contains(&include_path) {
+                files.push(include_path.clone());
+                pending_files.push(include_path);
+            }
+        }
+    }
+    ConfigSources { files, directories }
+}
+
+/// Parses a config file and expands its paths
+/// relative paths in the config are relative to the config file's directory
+pub fn load_config_file(path: &Path) -> Result<Config, ConfigError> {
+    let mut config = parse_config_file(path)?;
+    let absolute_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let base_dir = absolute_path.parent().unwrap_or(Path::new("."));
+    config.expand_paths(base_dir);
+    Ok(config)
+}
+
+/// Warnings are printed, errors make the whole config invalid
+pub fn validate_layered_config(config: Config, description: &str) -> Result<Config, ConfigError> {
+    let (errors, warnings): (Vec<ConfigIssue>, Vec<ConfigIssue>) = config
+        .validate()
+        .into_iter()
+        .partition(|issue| issue.severity == Severity::Error);
+    warnings.iter().for_each(|warning| warn!("{}: {}", warning.key, warning.message));
+    if !errors.is_empty() {
+        return Err(ConfigError::Invalid(description.to_string(), errors));
+    }
+    Ok(config)
+}
+
+/// Loads the config chain like the daemon does, with includes and drop-ins, and validates the layered config
+/// Every issue comes with the place that set its key, "path:line:column" or only the path when the key cannot be located
+pub fn check_config_chain(config_files: &[PathBuf]) -> Result<Vec<(ConfigIssue, Option<String>)>, ConfigError> {
+    let mut config = Config::default();
+    for layer in collect_config_layers(config_files)? {
+        config.layer(layer.config);
+    }
+    let origins = config_origins(config_files, None, &config)?;
+
+    let located_issues = config
+        .validate()
+        .into_iter()
+        .map(|issue| {
+            let location = key_origin(&origins, &issue.key)
+                .filter(|origin| Path::new(origin).is_file())
+                .map(|origin| locate_in_file(origin, &issue.key));
+            (issue, location)
+        })
+        .collect();
+    Ok(located_issues)
+}
+
+/// The layer that set a key, or the closest parent key when the key itself is missing, e.g. a missing message
+fn key_origin<'a>(origins: &'a BTreeMap<String, String>, key: &str) -> Option<&'a str> {
+    let mut key = key;
+    loop {
+        if let Some(origin) = origins.get(key) {
+            return Some(origin);
+        }
+        let table_prefix = format!("{key}.");
+        if let Some((_, origin)) = origins.range(table_prefix.clone()..).next()
+            .filter(|(origin_key, _)| origin_key.starts_with(&table_prefix)) {
+            return Some(origin);
+        }
+        key = key.rsplit_once('.')?.0;
+    }
+}
+
+/// "path:line:column" of a key, only the path if the file is not toml or the key is not in it
+fn locate_in_file(file_path: &str, key: &str) -> String {
+    let position = match ConfigFormat::from_path(Path::new(file_path)) {
+        // the file was just parsed, so reading it again only fails in a race
+        ConfigFormat::Toml => fs::read_to_string(file_path).ok().and_then(|source| locate_key(&source, key)),
+        _ => None,
     };
-    let path_to_config = home_directory.join(".config").join("spider-volt");
-    // create path if it does not exit ~/.config/spider-volt
-    match fs::create_dir_all(&path_to_config) {
-        Ok(()) => (),
+    match position {
+        Some((line, column)) => format!("{file_path}:{line}:{column}"),
+        None => file_path.to_string(),
+    }
+}
+
+/// Checks the config chain the daemon would load from `file_path`, printing every problem found
+/// Returns false if the config has errors, warnings alone do not fail the check
+/// The report is the output of --check-config, so it is written to stdout and stderr directly
+/// instead of going through the logger, which --quiet or RUST_LOG could silence
+pub fn check_config_file(file_path: &str) -> bool {
+    // A closed stderr must not hide the result, which is still given by the return value
+    let mut stderr = io::stderr().lock();
+    let issues = match check_config_chain(&config_file_chain(file_path)) {
+        Ok(issues) => issues,
         Err(error) => {
-            eprintln!("Could not create the path to the config file");
-            eprintln!("{:?}", path_to_config); 
-            eprintln!("{:?}", error);
-            return "".to_string();
+            let _ = writeln!(stderr, "error: {error}");
+            return false;
         }
     };
-    path_to_config.join("config.toml").display().to_string()
-}
 
+    for (issue, location) in &issues {
+        let _ = writeln!(stderr, "{issue}");
+        if let Some(location) = location {
+            let _ = writeln!(stderr, "  --> {location}");
+        }
+    }
 
-pub fn parse_toml(path: &str) -> Config {
-    fs::read_to_string(path)
-        .ok()
-        .and_then(|file| { toml::from_str(&file).ok()})
-        .unwrap_or_else(|| { 
-            eprintln!("Failed to load config.toml; Check Syntax\nusing defaults");
-            Config::default()})
+    let errors = issues.iter().filter(|(issue, _)| issue.severity == Severity::Error).count();
+    let warnings = issues.len() - errors;
+    if errors == 0 {
+        let _ = writeln!(io::stdout().lock(), "{file_path}: OK ({warnings} warnings)");
+    } else {
+        let _ = writeln!(stderr, "{file_path}: {errors} errors, {warnings} warnings");
+    }
+    errors == 0
 }
 
+/// Finds the line and column (1-based) of a dotted key in a toml document
+/// If the full key is missing, the closest parent that exists is used
+pub fn locate_key(source: &str, key: &str) -> Option<(usize, usize)> {
+    let document = DeTable::parse(source).ok()?;
+    let mut table = document.get_ref();
+    let mut span = None;
 
-pub fn print_config_toml_with_message(config: &Config, message: &str) -> Result<(), toml::ser::Error> {
-    println!("{}\n",message);
-    let toml = toml::to_string_pretty(config)?;
-    println!("{toml}");
-    Ok(())
+    for segment in key.split('.') {
+        let Some((found_key, value)) = table
+            .iter()
+            .find(|(table_key, _)| table_key.get_ref() == segment) else { break };
+        span = Some(found_key.span());
+        match value.get_ref() {
+            DeValue::Table(inner_table) => table = inner_table,
+            _ => break,
+        }
+    }
+
+    let offset = span?.start;
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = offset - before.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+    Some((line, column))
 }
 
-pub fn handle_config_file(file_path: &str) -> Config {
-    let path = Path::new(file_path);
-    if !path.exists() {
-        eprintln!("Could not find config.toml, Using Defaults");
-        return Config::default();
-    }
-    parse_toml(file_path)
-}
-
-pub fn create_default_config_file(file_path: &str){
-    let path = Path::new(file_path);
-    match fs::OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(path) {
-            Ok(_file) => {
-                match toml::to_string_pretty(&Config::default()) {
-                    Ok(toml_) => {
-                        match fs::write(file_path, toml_) {
-                            Ok(()) => (),
-                            Err(error) => eprintln!("{}", error)
-                        }
-                    },
-                    Err(error) => eprintln!("{}", error)
-                };
-            },
-            Err(error) => eprintln!("{}", error)
-        };
-    println!("Creating a config file using defaults");
+/// Writes the annotated config template to `file_path`, or `config` if one is given, in the format of its extension
+/// `init` passes the config built from its answers, `--create-config` writes the template
+/// An existing file is only replaced if `force` is set, it is copied to a backup next to it first
+/// Returns false and prints the error if the file could not be written
+pub fn create_default_config_file(file_path: &str, config: Option<&Config>, force: bool) -> bool {
+    let format = ConfigFormat::from_path(Path::new(file_path));
+    let config_str = match config {
+        Some(config) => format.serialize(config),
+        None => config_template(format),
+    };
+    let config_str = match config_str {
+        Ok(config_str) => config_str,
+        Err(error) => {
+            error!("{error}");
+            return false;
+        }
+    };
+    if let Err(error) = write_config_file(Path::new(file_path), &config_str, force) {
+        error!("Could not create {file_path}\n\t {error}");
+        return false;
+    }
+    match config {
+        Some(_) => println!("Created {file_path}"),
+        None => println!("Created {file_path} using defaults"),
+    }
+    true
+}
+
+fn write_config_file(path: &Path, config_str: &str, force: bool) -> io::Result<()> {
+    // create path if it does not exist, e.g. ~/.config/spider-volt
+    if let Some(config_dir) = path.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
+    if path.exists() {
+        if !force {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                "the file already exists, use --force to overwrite it, a backup is kept"));
+        }
+        let backup_path = backup_path(path);
+        fs::copy(path, &backup_path)?;
+        println!("Backed up {} to {}", path.display(), backup_path.display());
+    }
+    fs::write(path, config_str)
+}
 
-}
\ No newline at end of file
+/// `<file_path>.bak`, or `<file_path>.bak.1`, `<file_path>.bak.2`, ... so an earlier backup is never overwritten
+fn backup_path(path: &Path) -> PathBuf {
+    let backup_path = |suffix: String| {
+        let mut backup_path = path.as_os_str().to_os_string();
+        backup_path.push(suffix);
+        PathBuf::from(backup_path)
+    };
+    (0..)
+        .map(|number| backup_path(if number == 0 { ".bak".to_string() } else { format!(".bak.{number}") }))
+        .find(|backup_path| !backup_path.exists())
+        .unwrap_or_else(|| backup_path(".bak".to_string()))
+}
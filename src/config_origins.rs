@@ -0,0 +1,99 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use toml_edit::{DocumentMut, Item, TableLike};
+
+use crate::config::Config;
+use crate::config_file_manager::{collect_config_layers, ConfigError, ConfigFormat};
+
+/// Origin of the values that no config file sets
+const DEFAULT_ORIGIN: &str = "built-in default";
+const OVERRIDES_ORIGIN: &str = "environment or command line";
+
+/// Finds the layer that set each value of the effective config, keyed by dotted key
+/// Follows the same order as `load_config_chain`: defaults, files, overrides, profile, overrides
+pub fn config_origins(config_files: &[PathBuf], overrides: Option<&Config>, effective_config: &Config)
+    -> Result<BTreeMap<String, String>, ConfigError> {
+    let mut origins = BTreeMap::new();
+    record_origins(&mut origins, &Config::default(), DEFAULT_ORIGIN);
+    for layer in collect_config_layers(config_files)? {
+        record_origins(&mut origins, &layer.config, &layer.path.display().to_string());
+    }
+    if let Some(overrides) = overrides {
+        record_origins(&mut origins, overrides, OVERRIDES_ORIGIN);
+    }
+    if let Some(profile_name) = effective_config.active_profile()
+        && let Some(profile) = effective_config.profile(profile_name) {
+        record_origins(&mut origins, profile, &format!("profile {profile_name}"));
+        if let Some(overrides) = overrides {
+            record_origins(&mut origins, overrides, OVERRIDES_ORIGIN);
+        }
+    }
+    Ok(origins)
+}
+
+/// Every value set in `config` now comes from `origin`
+fn record_origins(origins: &mut BTreeMap<String, String>, config: &Config, origin: &str) {
+    // Through a string since thresholds are integer keys, which only the string serializer turns into strings
+    let Ok(config_str) = toml::to_string(config) else { return };
+    let Ok(table) = config_str.parse::<toml::Table>() else { return };
+    let mut keys = Vec::new();
+    leaf_keys(&table, "", &mut keys);
+    for key in keys {
+        origins.insert(key, origin.to_string());
+    }
+}
+
+/// Dotted keys of every value that is not a table, empty tables count as values since they disable a section
+fn leaf_keys(table: &toml::Table, prefix: &str, keys: &mut Vec<String>) {
+    for (key, value) in table {
+        let key = dotted_key(prefix, key);
+        match value {
+            toml::Value::Table(inner_table) if !inner_table.is_empty() => leaf_keys(inner_table, &key, keys),
+            _ => keys.push(key),
+        }
+    }
+}
+
+/// The config as toml, with a comment after every value naming the layer it came from
+pub fn annotate_config_origins(config: &Config, origins: &BTreeMap<String, String>) -> Result<String, String> {
+    let config_str = ConfigFormat::Toml.serialize(config)?;
+    let mut document = config_str.parse::<DocumentMut>().map_err(|error| error.to_string())?;
+    annotate_table(document.as_table_mut(), "", origins);
+    Ok(document.to_string())
+}
+
+fn annotate_table(table: &mut dyn TableLike, prefix: &str, origins: &BTreeMap<String, String>) {
+    for (key, item) in table.iter_mut() {
+        let key = dotted_key(prefix, key.get());
+        let origin = origins.get(&key).map(|origin| format!(" # {origin}"));
+        match item {
+            Item::Value(value) => {
+                if let Some(origin) = origin {
+                    value.decor_mut().set_suffix(origin);
+                }
+            },
+            // An empty table disables a section, so it is annotated like a value
+            Item::Table(inner_table) if inner_table.is_empty() => {
+                if let Some(origin) = origin {
+                    inner_table.decor_mut().set_suffix(origin);
+                }
+            },
+            Item::Table(inner_table) => annotate_table(inner_table, &key, origins),
+            // Lists are set as a whole, e.g. profile_rules
+            Item::ArrayOfTables(array) => {
+                if let Some(origin) = origin {
+                    array.iter_mut().for_each(|inner_table| inner_table.decor_mut().set_suffix(origin.clone()));
+                }
+            },
+            Item::None => (),
+        }
+    }
+}
+
+fn dotted_key(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
@@ -0,0 +1,118 @@
+use std::{env, ffi::OsString, path::Path};
+
+use log::warn;
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::config::Config;
+use crate::config_editor::{find_or_create_table, parse_value};
+use crate::config_file_manager::{ConfigError, ConfigFormat};
+use crate::path_expansion::ExpandPaths;
+
+/// Every environment variable with this prefix overrides a config value
+/// e.g. `SPIDER_VOLT_NOTIFICATION_TIME=3000`
+const ENV_PREFIX: &str = "SPIDER_VOLT_";
+/// Separates nested keys in environment variable names,
+/// e.g. `SPIDER_VOLT_LOW_BATTERY_LEVELS__15__MESSAGE` is `low_battery_levels.15.message`
+const ENV_KEY_SEPARATOR: &str = "__";
+
+/// Describes where the overrides come from in error messages
+const OVERRIDES_DESCRIPTION: &str = "the environment and command line overrides";
+
+/// Builds the config overrides from the environment and the `--low` and `--high` flags
+/// Returns None if nothing is overridden, variables that are not valid UTF-8 are skipped
+pub fn config_overrides(
+        env_vars: impl Iterator<Item = (OsString, OsString)>,
+        low_overrides: &[(u8, String)],
+        high_overrides: &[(u8, String)],
+    ) -> Result<Option<Config>, ConfigError> {
+    let mut overrides: Vec<(String, Value)> = env_vars
+        .filter_map(|(name, value)| {
+            let key = env_var_key(name.to_str()?)?;
+            match value.to_str() {
+                Some(value) => Some((key.clone(), override_value(&key, value))),
+                None => {
+                    warn!("Ignoring {}, its value is not valid UTF-8", name.to_string_lossy());
+                    None
+                }
+            }
+        })
+        .collect();
+    // The flags come after the environment, so they take priority
+    for (section, thresholds) in [("low_battery_levels", low_overrides), ("high_battery_levels", high_overrides)] {
+        overrides.extend(thresholds
+            .iter()
+            .map(|(threshold, message)| (format!("{section}.{threshold}.message"), Value::from(message.as_str()))));
+    }
+    if overrides.is_empty() {
+        return Ok(None);
+    }
+
+    let mut document = DocumentMut::new();
+    for (key, value) in overrides {
+        insert_override(&mut document, &key, value)?;
+    }
+
+    let mut config = ConfigFormat::Toml.parse(&document.to_string())
+        .map_err(|error| ConfigError::Parse(OVERRIDES_DESCRIPTION.to_string(), error))?;
+    // Relative paths in overrides are relative to where spider-volt was started
+    if let Ok(current_dir) = env::current_dir() {
+        config.expand_paths(Path::new(&current_dir));
+    }
+    Ok(Some(config))
+}
+
+fn insert_override(document: &mut DocumentMut, key: &str, value: Value) -> Result<(), ConfigError> {
+    let segments: Vec<&str> = key.split('.').collect();
+    let (last_segment, parent_segments) = segments.split_last().unwrap_or((&"", &[]));
+    let table = find_or_create_table(document.as_table_mut(), parent_segments)
+        .map_err(|error| ConfigError::Parse(OVERRIDES_DESCRIPTION.to_string(), format!("{key}: {error}")))?;
+    table.insert(last_segment, Item::Value(value));
+    Ok(())
+}
+
+/// Values are read as TOML, e.g. `3000` or `true`, a value that does not fit the key is read as a string instead,
+/// so `SPIDER_VOLT_LOW_BATTERY_LEVELS__20__MESSAGE=42` is the message "42"
+/// A value that fits neither way is kept as it was parsed, so the error names the type the key expects
+fn override_value(key: &str, raw_value: &str) -> Value {
+    let value = parse_value(raw_value);
+    if value.is_str() || fits_config(key, value.clone()) {
+        return value;
+    }
+    let string_value = Value::from(raw_value);
+    if fits_config(key, string_value.clone()) { string_value } else { value }
+}
+
+/// Whether a config with only this key set parses
+fn fits_config(key: &str, value: Value) -> bool {
+    let mut document = DocumentMut::new();
+    insert_override(&mut document, key, value).is_ok() && ConfigFormat::Toml.parse(&document.to_string()).is_ok()
+}
+
+/// `SPIDER_VOLT_LOW_BATTERY_LEVELS__15__MESSAGE` -> `low_battery_levels.15.message`
+/// Profile names keep their case, `SPIDER_VOLT_PROFILES__Desk__MUTE_SOUNDS` -> `profiles.Desk.mute_sounds`
+fn env_var_key(name: &str) -> Option<String> {
+    let key = name.strip_prefix(ENV_PREFIX)?;
+    if key.is_empty() {
+        return None;
+    }
+    let mut segments: Vec<String> = key.split(ENV_KEY_SEPARATOR).map(str::to_lowercase).collect();
+    if segments[0] == "profiles"
+        && let Some(profile_name) = key.split(ENV_KEY_SEPARATOR).nth(1) {
+        segments[1] = profile_name.to_string();
+    }
+    Some(segments.join("."))
+}
+
+/// Parses the value of `--low` and `--high`, e.g. `15:Battery low`
+pub fn parse_threshold_message(value: &str) -> Result<(u8, String), String> {
+    let (threshold, message) = value.split_once(':')
+        .ok_or_else(|| "expected THRESHOLD:MESSAGE, e.g. 15:\"Battery low\"".to_string())?;
+    let threshold = threshold.trim().trim_end_matches('%').parse::<u8>()
+        .ok()
+        .filter(|threshold| *threshold <= 100)
+        .ok_or_else(|| format!("\"{threshold}\" is not a percentage between 0 and 100"))?;
+    if message.trim().is_empty() {
+        return Err("the message cannot be empty".to_string());
+    }
+    Ok((threshold, message.to_string()))
+}
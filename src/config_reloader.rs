@@ -0,0 +1,107 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+};
+
+use inotify::{Inotify, WatchDescriptor, WatchMask};
+use log::warn;
+use signal_hook::consts::SIGHUP;
+
+//  ----------------------------------------------------
+//  ConfigReloader Struct and Implementation
+//  ----------------------------------------------------
+/// Detects when the config should be reloaded,
+/// either because a watched file changed or because SIGHUP was received
+pub struct ConfigReloader {
+    inotify: Option<Inotify>,
+    /// None watches every file in the directory
+    watched_files: Vec<(WatchDescriptor, Option<OsString>)>,
+    sighup_received: Arc<AtomicBool>,
+    buffer: [u8; 4096],
+}
+
+impl ConfigReloader {
+
+    pub fn new(files_to_watch: &[PathBuf]) -> Self {
+        let sighup_received = Arc::new(AtomicBool::new(false));
+        if let Err(error) = signal_hook::flag::register(SIGHUP, Arc::clone(&sighup_received)) {
+            warn!("Could not register SIGHUP handler, reloading on SIGHUP is disabled\n\t {error:?}");
+        }
+
+        let mut reloader = ConfigReloader {
+            inotify: None,
+            watched_files: Vec::new(),
+            sighup_received,
+            buffer: [0; 4096],
+        };
+
+        match Inotify::init() {
+            Ok(inotify) => reloader.inotify = Some(inotify),
+            Err(error) => {
+                warn!("Could not initialize inotify, config file changes will not be detected\n\t {error:?}");
+            }
+        }
+        reloader.watch(files_to_watch, &[]);
+        reloader
+    }
+
+    /// Adds watches for files and directories that are not watched yet,
+    /// called again after a reload since the config can include new files
+    pub fn watch(&mut self, files_to_watch: &[PathBuf], directories_to_watch: &[PathBuf]) {
+        for file in files_to_watch {
+            if let (Some(directory), Some(file_name)) = (file.parent(), file.file_name()) {
+                self.add_watch(directory, Some(file_name.to_os_string()));
+            }
+        }
+        for directory in directories_to_watch {
+            self.add_watch(directory, None);
+        }
+    }
+
+    /// Watches the file's directory rather than the file itself,
+    /// editors usually replace the file on save which would drop a watch on the file
+    fn add_watch(&mut self, directory: &Path, file_name: Option<OsString>) {
+        let Some(inotify) = self.inotify.as_mut() else { return };
+
+        let mask = WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE;
+        match inotify.watches().add(directory, mask) {
+            // Watching the same directory again returns the same watch descriptor
+            Ok(watch_descriptor) => {
+                let watched_file = (watch_descriptor, file_name);
+                if !self.watched_files.contains(&watched_file) {
+                    self.watched_files.push(watched_file);
+                }
+            },
+            // A missing directory is not an error, the file can still be reloaded with SIGHUP
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => {
+                warn!("Could not watch {}\n\t {error:?}", directory.display());
+            }
+        }
+    }
+
+    /// Returns true if a reload was requested since the last call, never blocks
+    pub fn reload_requested(&mut self) -> bool {
+        let mut requested = self.sighup_received.swap(false, Ordering::Relaxed);
+
+        if let Some(inotify) = self.inotify.as_mut() {
+            // Drain every pending event, multiple events for a single save should only cause one reload
+            while let Ok(events) = inotify.read_events(&mut self.buffer) {
+                let mut has_events = false;
+                for event in events {
+                    has_events = true;
+                    requested |= self.watched_files.iter().any(|(watch_descriptor, file_name)| {
+                        *watch_descriptor == event.wd
+                            && file_name.as_ref().is_none_or(|file_name| event.name == Some(file_name.as_os_str()))
+                    });
+                }
+                if !has_events {
+                    break;
+                }
+            }
+        }
+        requested
+    }
+}
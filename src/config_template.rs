@@ -0,0 +1,142 @@
+use crate::config::{Config, UrgencyLevel};
+use crate::config_file_manager::ConfigFormat;
+
+/// The annotated toml template, `{urgency_levels}` is replaced with the valid urgency levels
+/// Lines starting with "# " explain the field below them,
+/// lines starting with "#" and no space are examples that can be uncommented as is
+/// The uncommented values must match `Config::template`
+const TOML_TEMPLATE: &str = r##"# spider-volt configuration
+#
+# Every field is optional, this file is layered on top of the built-in defaults:
+#   notification_time = 5000
+#   [low_battery_levels.20]
+#   message = "Battery Low"
+#
+# Lines starting with "# " explain the field below them,
+# lines starting with "#" are examples, remove the "#" to use them
+#
+# Icons are icon names, e.g. from ~/.local/share/icons, or paths to an image
+# Paths can use ~, $VAR and ${VAR:-default}, relative paths are relative to this file
+# Urgency levels are one of {urgency_levels}, in any case
+#
+# Files in the config.d directory next to this file are layered on top of it, in lexical order
+
+# Other config files to layer below this one, in order
+#include = ["~/.config/spider-volt/sounds.toml"]
+
+# How long notifications are shown for in ms, defaults to 5000
+#notification_time = 8000
+
+# Disables every notification sound
+#mute_sounds = true
+
+# Only sends notifications with at least this urgency, one of {urgency_levels}
+#minimum_urgency = "Normal"
+
+# Sends a notification when a profile rule switches the profile
+#announce_profile_switches = true
+
+# Fields inherited by every notification that does not set them
+# [defaults.low] and [defaults.high] only apply to low and high battery notifications
+#[defaults]
+#notification_icon = "battery"
+#notification_sound = "~/.config/spider-volt/sounds/notification.ogg"
+#urgent_level = "Normal"
+#
+#[defaults.low]
+#urgent_level = "Critical"
+#
+#[defaults.high]
+#urgent_level = "Low"
+
+# Notifications sent while discharging, the number is the battery percentage (0 to 100)
+# Add one table per threshold, `low_battery_levels = {}` disables the default 20% notification
+# The default 20% notification is already sent, uncomment this table to change it
+#[low_battery_levels.20]
+# Required, unless a lower layer already sets it
+#message = "Battery Low"
+#notification_icon = "battery-low"
+#notification_sound = "~/.config/spider-volt/sounds/battery_low.ogg"
+# One of {urgency_levels}, inherited from [defaults] if not set
+#urgent_level = "Critical"
+
+#[low_battery_levels.10]
+#message = "Battery Critically Low"
+#notification_icon = "battery-caution"
+#urgent_level = "Critical"
+
+# Notifications sent while charging, the number is the battery percentage (0 to 100)
+[high_battery_levels.100]
+# Required, unless a lower layer already sets it
+message = "Battery Fully Charged"
+#notification_icon = "battery-full-charged"
+#notification_sound = "~/.config/spider-volt/sounds/battery_full.ogg"
+# One of {urgency_levels}, inherited from [defaults] if not set
+#urgent_level = "Low"
+
+# Notifications sent when the charger is plugged in or unplugged, both are off by default
+#[charger_notifications]
+# Notify when the charger is plugged in
+#charging = true
+#charging_icon = "battery-charging"
+#plugged_sound = "~/.config/spider-volt/sounds/plugged.ogg"
+# Notify when the charger is unplugged
+#discharging = true
+#discharging_icon = "battery-discharging"
+#unplugged_sound = "~/.config/spider-volt/sounds/unplugged.ogg"
+# One of {urgency_levels}, inherited from [defaults] if not set
+#urgent_level = "Normal"
+
+# Named profiles, a profile is layered on top of the rest of this file while it is active
+# Select one with `spider-volt --profile NAME` or `spider-volt --set-profile NAME`
+#[profiles.quiet]
+#mute_sounds = true
+#minimum_urgency = "Critical"
+
+# Rules that activate a profile automatically, the first matching rule wins
+# Every condition is optional:
+#   days: any of "mon", "tue", "wed", "thu", "fri", "sat", "sun"
+#   start and end: "HH:MM", the window can go past midnight
+#   docked: true if the charger was connected for most of the last hour
+#[[profile_rules]]
+#profile = "quiet"
+#days = ["sat", "sun"]
+#start = "22:00"
+#end = "07:00"
+#docked = false
+
+# Output of `spider-volt bar`, placeholders are {percentage}, {state}, {time_remaining} and {profile}
+# The level is warning or critical at or below a low battery threshold, critical if that notification is Critical
+#[bar]
+#format = "{percentage}%"
+#tooltip_format = "{state}, {time_remaining}"
+#[bar.critical]
+#class = "critical"
+#color = "#ff5555"
+"##;
+
+/// The config template in the given format
+/// toml is annotated with comments, json has no comments so it is the plain `Config::template`
+pub fn config_template(format: ConfigFormat) -> Result<String, String> {
+    match format {
+        ConfigFormat::Toml => Ok(annotated_toml_template()),
+        ConfigFormat::Yaml => Ok(format!(
+            "# spider-volt configuration, every field is optional\n\
+             # run `spider-volt --print-config-template` for a commented toml template\n\
+             # Urgency levels are one of {}, in any case\n{}",
+            urgency_levels(), ConfigFormat::Yaml.serialize(&Config::template())?)),
+        ConfigFormat::Json => ConfigFormat::Json.serialize(&Config::template()),
+    }
+}
+
+fn annotated_toml_template() -> String {
+    TOML_TEMPLATE.replace("{urgency_levels}", &urgency_levels())
+}
+
+fn urgency_levels() -> String {
+    UrgencyLevel::VARIANTS
+        .iter()
+        .map(|variant| format!("\"{variant}\""))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
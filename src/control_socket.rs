@@ -0,0 +1,344 @@
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Lines, Read, Write},
+    os::unix::{fs::PermissionsExt, net::{UnixListener, UnixStream}},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::config_file_manager::xdg_dir_from_env;
+
+const SOCKET_FILE_NAME: &str = "spider-volt.sock";
+
+/// Bumped whenever a request or response changes in a way older clients or daemons cannot read
+pub const PROTOCOL_VERSION: u64 = 1;
+
+/// How long a client may take to send its request, and the daemon to write a response
+const IO_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connections still sending their request, more connections wait in the listen backlog until these are done
+const MAX_PENDING_CONNECTIONS: usize = 32;
+
+/// A request is a single short line, anything longer is not a request
+const MAX_REQUEST_LENGTH: usize = 64 * 1024;
+
+/// `$XDG_RUNTIME_DIR/spider-volt.sock`, there is no fallback since the runtime dir is private to the user
+pub fn socket_path() -> Option<PathBuf> {
+    xdg_dir_from_env("XDG_RUNTIME_DIR").map(|runtime_dir| runtime_dir.join(SOCKET_FILE_NAME))
+}
+
+// ----------------------------------------------------------------
+// Protocol, one JSON object per line in each direction
+//   {"version": 1, "command": "snooze", "minutes": 30}
+//   {"version": 1, "ok": true}
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    Reload,
+    /// No notifications are sent for this many minutes, 0 ends the snooze
+    Snooze { minutes: u64 },
+    /// "default" switches back to no profile
+    SwitchProfile { profile: String },
+    TestNotification,
+    Quit,
+    /// Keeps the connection open and streams a `DaemonEvent` per line after the response
+    Watch,
+}
+
+/// What the daemon is currently doing, sent in reply to `status`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct DaemonStatus {
+    pub percentage: i32,
+    pub state: String,
+    /// Seconds until empty while discharging or until full while charging
+    #[serde(default)]
+    pub time_remaining: Option<u64>,
+    pub profile: String,
+    /// Thresholds that already fired since the last charger state change
+    pub fired_thresholds: Vec<u8>,
+    /// Local time in RFC 3339, None if not snoozed
+    pub snoozed_until: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Response {
+    pub version: u64,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<DaemonStatus>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response { version: PROTOCOL_VERSION, ok: true, error: None, status: None }
+    }
+
+    pub fn error(error: impl Into<String>) -> Self {
+        Response { version: PROTOCOL_VERSION, ok: false, error: Some(error.into()), status: None }
+    }
+
+    pub fn status(status: DaemonStatus) -> Self {
+        Response { status: Some(status), ..Response::ok() }
+    }
+}
+
+/// The version is checked before the command, so a newer client gets a clear error instead of "unknown command"
+pub fn parse_request(line: &str) -> Result<Request, String> {
+    let request: serde_json::Value = serde_json::from_str(line)
+        .map_err(|error| format!("the request is not valid JSON: {error}"))?;
+    let version = request.get("version")
+        .and_then(serde_json::Value::as_u64)
+        .ok_or_else(|| "the request has no \"version\"".to_string())?;
+    if version != PROTOCOL_VERSION {
+        return Err(format!("unsupported protocol version {version}, the daemon speaks version {PROTOCOL_VERSION}"));
+    }
+    serde_json::from_value(request).map_err(|error| format!("invalid request: {error}"))
+}
+
+fn request_line(request: &Request) -> Result<String, serde_json::Error> {
+    let mut request = serde_json::to_value(request)?;
+    if let Some(fields) = request.as_object_mut() {
+        fields.insert("version".to_string(), PROTOCOL_VERSION.into());
+    }
+    Ok(format!("{request}\n"))
+}
+
+// ----------------------------------------------------------------
+// Control Socket Errors
+// ----------------------------------------------------------------
+#[derive(Debug)]
+pub enum ControlError {
+    /// XDG_RUNTIME_DIR is not set
+    NoRuntimeDir,
+    /// Nothing is listening on the socket
+    NotRunning(PathBuf),
+    AlreadyRunning(PathBuf),
+    Io(PathBuf, io::Error),
+    InvalidResponse(String),
+    /// The daemon answered with ok = false
+    Refused(String),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::NoRuntimeDir => write!(f, "XDG_RUNTIME_DIR is not set, the control socket is disabled"),
+            ControlError::NotRunning(path) => write!(f, "spider-volt is not running, nothing listens on {}", path.display()),
+            ControlError::AlreadyRunning(path) => write!(f, "spider-volt is already running, {} is in use", path.display()),
+            ControlError::Io(path, error) => write!(f, "Could not use {}: {error}", path.display()),
+            ControlError::InvalidResponse(error) => write!(f, "The daemon sent an invalid response: {error}"),
+            ControlError::Refused(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+// Server, polled by the daemon loop
+// ----------------------------------------------------------------
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    /// Requests are read without blocking, so a request that arrives in pieces is completed over several polls
+    pending_connections: Vec<PendingConnection>,
+}
+
+impl ControlSocket {
+    /// A socket file left behind by a daemon that crashed is replaced,
+    /// a socket that still accepts connections belongs to a running daemon and is an error
+    pub fn bind(path: &Path) -> Result<Self, ControlError> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(ControlError::AlreadyRunning(path.to_path_buf()));
+            }
+            fs::remove_file(path).map_err(|error| ControlError::Io(path.to_path_buf(), error))?;
+        }
+        let io_error = |error| ControlError::Io(path.to_path_buf(), error);
+        let listener = UnixListener::bind(path).map_err(io_error)?;
+        // The runtime dir is already private, this also covers a socket path passed in from elsewhere
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        Ok(ControlSocket { listener, path: path.to_path_buf(), pending_connections: Vec::new() })
+    }
+
+    /// Every request that arrived completely, never blocks
+    /// Requests that cannot be read, or are not complete after IO_TIMEOUT, are answered with an error right away
+    pub fn accept(&mut self) -> Vec<(Request, ControlConnection)> {
+        while self.pending_connections.len() < MAX_PENDING_CONNECTIONS {
+            match self.listener.accept() {
+                // Linux does not pass the listener's non-blocking mode on to accepted sockets
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.pending_connections.push(PendingConnection::new(stream)),
+                    Err(error) => warn!("Could not read a control connection without blocking\n\t {error:?}"),
+                },
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    warn!("Could not accept a control connection\n\t {error:?}");
+                    break;
+                }
+            }
+        }
+
+        let mut requests = Vec::new();
+        let mut still_pending = Vec::new();
+        for mut pending_connection in self.pending_connections.drain(..) {
+            let Some(request) = pending_connection.read_request() else {
+                still_pending.push(pending_connection);
+                continue;
+            };
+            let Some(connection) = ControlConnection::new(pending_connection.stream) else { continue };
+            match request {
+                Ok(request) => requests.push((request, connection)),
+                Err(error) => connection.reply(Response::error(error)),
+            }
+        }
+        self.pending_connections = still_pending;
+        requests
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A connection whose request has not arrived completely yet
+struct PendingConnection {
+    stream: UnixStream,
+    received: Vec<u8>,
+    accepted_at: Instant,
+}
+
+impl PendingConnection {
+    fn new(stream: UnixStream) -> Self {
+        PendingConnection { stream, received: Vec::new(), accepted_at: Instant::now() }
+    }
+
+    /// Reads whatever arrived since the last poll, None while the request is incomplete
+    /// A client that closes its end without a newline still sends a request, like `printf` piped into socat
+    fn read_request(&mut self) -> Option<Result<Request, String>> {
+        let mut buffer = [0; 4096];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Some(self.parse_received()),
+                Ok(length) => {
+                    self.received.extend_from_slice(&buffer[..length]);
+                    if let Some(newline) = self.received.iter().position(|byte| *byte == b'\n') {
+                        self.received.truncate(newline);
+                        return Some(self.parse_received());
+                    }
+                    if self.received.len() > MAX_REQUEST_LENGTH {
+                        return Some(Err(format!("the request is longer than {MAX_REQUEST_LENGTH} bytes")));
+                    }
+                },
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Some(Err(format!("could not read the request: {error}"))),
+            }
+        }
+        if self.accepted_at.elapsed() >= IO_TIMEOUT {
+            return Some(Err(format!("no complete request within {IO_TIMEOUT:?}")));
+        }
+        None
+    }
+
+    fn parse_received(&self) -> Result<Request, String> {
+        match str::from_utf8(&self.received) {
+            Ok(line) => parse_request(line),
+            Err(_) => Err("the request is not valid UTF-8".to_string()),
+        }
+    }
+}
+
+/// The stream a request came from, the response is written back to it
+pub struct ControlConnection {
+    stream: UnixStream,
+}
+
+impl ControlConnection {
+    /// Responses are written blocking, with a timeout so a client that stopped reading does not stall the daemon loop
+    fn new(stream: UnixStream) -> Option<Self> {
+        stream.set_nonblocking(false).ok()?;
+        stream.set_write_timeout(Some(IO_TIMEOUT)).ok()?;
+        Some(ControlConnection { stream })
+    }
+
+    pub fn reply(mut self, response: Response) {
+        // The client may have given up already, which only concerns the client
+        self.send(&response);
+    }
+
+    /// Writes one line of JSON, returns false once the client is gone or stopped reading
+    pub fn send(&mut self, line: &impl Serialize) -> bool {
+        let Ok(line) = serde_json::to_string(line) else { return false };
+        writeln!(self.stream, "{line}").is_ok()
+    }
+}
+
+// ----------------------------------------------------------------
+// Client
+// ----------------------------------------------------------------
+/// Sends a request to the daemon listening on `path` and waits for its response
+pub fn send_request(path: &Path, request: &Request) -> Result<Response, ControlError> {
+    exchange(path, request).map(|(response, _)| response)
+}
+
+/// Starts watching the daemon, every line is a `DaemonEvent` as JSON, the lines end when the daemon stops
+pub fn watch_events(path: &Path) -> Result<Lines<BufReader<UnixStream>>, ControlError> {
+    let (_, reader) = exchange(path, &Request::Watch)?;
+    // Events can be minutes apart
+    reader.get_ref().set_read_timeout(None).map_err(|error| ControlError::Io(path.to_path_buf(), error))?;
+    Ok(reader.lines())
+}
+
+/// Returns the response and the reader, which is kept for requests that are followed by more lines
+fn exchange(path: &Path, request: &Request) -> Result<(Response, BufReader<UnixStream>), ControlError> {
+    let io_error = |error| ControlError::Io(path.to_path_buf(), error);
+    let mut stream = UnixStream::connect(path).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused => ControlError::NotRunning(path.to_path_buf()),
+        _ => ControlError::Io(path.to_path_buf(), error),
+    })?;
+    // The daemon answers between two battery checks, which are a second apart
+    stream.set_read_timeout(Some(IO_TIMEOUT * 5)).map_err(io_error)?;
+
+    let request = request_line(request).map_err(|error| ControlError::InvalidResponse(error.to_string()))?;
+    stream.write_all(request.as_bytes()).map_err(io_error)?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).map_err(io_error)?;
+    let response: Response = serde_json::from_str(&line)
+        .map_err(|error| ControlError::InvalidResponse(error.to_string()))?;
+    if !response.ok {
+        return Err(ControlError::Refused(response.error.unwrap_or_default()));
+    }
+    Ok((response, reader))
+}
+
+/// Asks a running daemon to reload the config and waits until it did
+/// Without a socket there is no daemon to ask, it still notices the change through inotify
+pub fn request_reload() -> Result<(), ControlError> {
+    let Some(path) = socket_path() else { return Ok(()) };
+    match send_request(&path, &Request::Reload) {
+        Ok(_) | Err(ControlError::NotRunning(_)) => Ok(()),
+        Err(error) => Err(error),
+    }
+}
+
+/// Asks a running daemon to switch to the profile, the daemon also saves it for the next restart
+/// Returns false if no daemon is running, the profile then has to be saved without it
+pub fn request_profile_switch(profile: &str) -> Result<bool, ControlError> {
+    let Some(path) = socket_path() else { return Ok(false) };
+    match send_request(&path, &Request::SwitchProfile { profile: profile.to_string() }) {
+        Ok(_) => Ok(true),
+        Err(ControlError::NotRunning(_)) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
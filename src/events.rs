@@ -0,0 +1,65 @@
+use std::io::{self, Write};
+
+use log::{error, warn};
+use serde::Serialize;
+
+use crate::control_socket::{self, watch_events, ControlError, DaemonStatus};
+
+/// What happened in the daemon, streamed to `spider-volt watch` as one JSON object per line
+///   {"event": "threshold_crossed", "kind": "low", "threshold": 20, "percentage": 20}
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DaemonEvent {
+    /// Sent once when watching starts, so a widget does not have to wait for the first change
+    Status(DaemonStatus),
+    /// The charger state changed, Unknown states are already resolved to Charging or Discharging
+    StateChanged { state: String, previous_state: String, percentage: i32 },
+    PercentageChanged { percentage: i32, previous_percentage: i32 },
+    ThresholdCrossed { kind: NotificationKind, threshold: u8, percentage: i32 },
+    NotificationSent { kind: NotificationKind, summary: String, percentage: i32 },
+    /// `error` is set if the new config was invalid and the current config was kept
+    ConfigReloaded { profile: String, error: Option<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Low,
+    High,
+    Charger,
+}
+
+/// Prints every event of the running daemon until it stops, returns false if it could not be watched
+pub fn print_events() -> bool {
+    let events = control_socket::socket_path()
+        .ok_or(ControlError::NoRuntimeDir)
+        .and_then(|socket_path| watch_events(&socket_path));
+    let events = match events {
+        Ok(events) => events,
+        Err(error) => {
+            error!("{error}");
+            return false;
+        }
+    };
+
+    // The lines are passed through as sent, stdout is flushed after every line
+    for event in events {
+        match event {
+            Ok(event) => match writeln!(io::stdout().lock(), "{event}") {
+                Ok(()) => (),
+                // The reader is gone, e.g. `spider-volt watch | head -1` or a widget that stopped
+                Err(error) if error.kind() == io::ErrorKind::BrokenPipe => return true,
+                Err(error) => {
+                    error!("Could not print the event\n\t {error}");
+                    return false;
+                }
+            },
+            Err(error) => {
+                error!("Lost the connection to spider-volt: {error}");
+                return false;
+            }
+        }
+    }
+    warn!("spider-volt stopped");
+    false
+}
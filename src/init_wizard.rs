@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use crate::config::{BatteryNotification, ChargerNotification, Config, UrgencyLevel};
+use crate::config_file_manager::create_default_config_file;
+use crate::path_expansion::expand_file_path;
+
+#[cfg(not(test))]
+use crate::{audio::drain_audio_worker, notifications::{send_battery_notification, send_charger_notification}};
+
+#[cfg(test)]
+use crate::tests::{drain_audio_worker, send_battery_notification, send_charger_notification};
+
+/// Battery level shown in the charger notification previews
+const PREVIEW_BATTERY_LEVEL: i32 = 50;
+
+// ----------------------------------------------------------------
+// Prompt Trait, so the wizard can be driven by scripted input in tests
+// ----------------------------------------------------------------
+pub trait Prompt {
+    /// Shows the question and returns the trimmed answer, None once the input is closed
+    fn ask(&mut self, question: &str) -> Option<String>;
+
+    /// Shows a message that does not need an answer
+    fn say(&mut self, message: &str);
+}
+
+/// Reads answers line by line, e.g. from stdin
+pub struct TerminalPrompt<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> TerminalPrompt<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        TerminalPrompt { input, output }
+    }
+}
+
+impl<R: BufRead, W: Write> Prompt for TerminalPrompt<R, W> {
+    fn ask(&mut self, question: &str) -> Option<String> {
+        write!(self.output, "{question} ").ok()?;
+        self.output.flush().ok()?;
+
+        let mut answer = String::new();
+        match self.input.read_line(&mut answer) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(answer.trim().to_string()),
+        }
+    }
+
+    fn say(&mut self, message: &str) {
+        // Nothing useful can be done if the terminal is gone, the next ask fails anyway
+        let _ = writeln!(self.output, "{message}");
+    }
+}
+
+// ----------------------------------------------------------------
+// Init Wizard
+// ----------------------------------------------------------------
+/// Asks for the notifications to use and writes them to `config_path`,
+/// returns false if the wizard was aborted or the file could not be written
+pub fn run_init_wizard(prompt: &mut impl Prompt, config_path: &str, force: bool) -> bool {
+    if Path::new(config_path).exists() && !force {
+        prompt.say(&format!("{config_path} already exists, use `init --force` to replace it, a backup is kept"));
+        return false;
+    }
+
+    let base_dir = Path::new(config_path).parent().unwrap_or(Path::new("."));
+    let config = ask_config(prompt, base_dir);
+    // Previews play in the background, the last one should not be cut off when the wizard exits
+    drain_audio_worker();
+    let Some(config) = config else {
+        prompt.say("Input closed, no config file was written");
+        return false;
+    };
+
+    create_default_config_file(config_path, Some(&config), force)
+}
+
+/// Asks every question and builds the config from the answers, None if the input was closed
+pub fn ask_config(prompt: &mut impl Prompt, base_dir: &Path) -> Option<Config> {
+    prompt.say("Press enter to use the value in brackets, answer \"none\" to skip a notification");
+    let mut config = Config::default();
+
+    let low_thresholds = ask_thresholds(prompt, "Low battery thresholds in %, comma separated", "20, 10")?;
+    let mut low_battery_levels = BTreeMap::new();
+    if let Some(&lowest) = low_thresholds.first() {
+        let icon = ask_with_default(prompt, "Low battery icon", "battery-low")?;
+        let sound = ask_sound(prompt, "Low battery sound file", base_dir)?;
+        if ask_yes_no(prompt, "Preview the low battery notification?", true)? {
+            send_battery_notification(&(lowest as i32), "Battery Low", icon.as_deref().unwrap_or(""),
+                UrgencyLevel::Critical.into(), &preview_sound(&sound, base_dir), config.time());
+        }
+        for threshold in low_thresholds {
+            low_battery_levels.insert(threshold,
+                BatteryNotification::new("Battery Low", icon.clone(), sound.clone(), UrgencyLevel::Critical));
+        }
+    }
+    // An empty table disables the default 20% notification
+    config.low_battery_levels = Some(low_battery_levels);
+
+    let high_thresholds = ask_thresholds(prompt, "High battery thresholds in %, comma separated", "none")?;
+    if let Some(&highest) = high_thresholds.last() {
+        let icon = ask_with_default(prompt, "High battery icon", "battery-full-charged")?;
+        let sound = ask_sound(prompt, "High battery sound file", base_dir)?;
+        if ask_yes_no(prompt, "Preview the high battery notification?", true)? {
+            send_battery_notification(&(highest as i32), "Battery Charged", icon.as_deref().unwrap_or(""),
+                UrgencyLevel::Normal.into(), &preview_sound(&sound, base_dir), config.time());
+        }
+        config.high_battery_levels = Some(high_thresholds
+            .into_iter()
+            .map(|threshold| (threshold,
+                BatteryNotification::new("Battery Charged", icon.clone(), sound.clone(), UrgencyLevel::Normal)))
+            .collect());
+    } else {
+        // An empty table disables the default 100% notification
+        config.high_battery_levels = Some(BTreeMap::new());
+    }
+
+    let plugged = ask_charger_notification(prompt, "Charging", "plugged in", "battery-charging", base_dir, config.time())?;
+    let unplugged = ask_charger_notification(prompt, "Discharging", "unplugged", "battery-discharging", base_dir, config.time())?;
+    // Left out of the file when both are skipped, the defaults do not notify on charger changes either
+    config.charger_notifications = (plugged.is_some() || unplugged.is_some())
+        .then(|| ChargerNotification::new(plugged, unplugged));
+    Some(config)
+}
+
+/// Returns the (icon, sound) for a charger notification, Some(None) if the user does not want it
+fn ask_charger_notification(
+        prompt: &mut impl Prompt,
+        charging_state: &str,
+        event: &str,
+        default_icon: &str,
+        base_dir: &Path,
+        time: i32,
+    ) -> Option<Option<(Option<String>, Option<String>)>> {
+    if !ask_yes_no(prompt, &format!("Notify when the charger is {event}?"), false)? {
+        return Some(None);
+    }
+    let icon = ask_with_default(prompt, &format!("Icon when the charger is {event}"), default_icon)?;
+    let sound = ask_sound(prompt, &format!("Sound file when the charger is {event}"), base_dir)?;
+    if ask_yes_no(prompt, "Preview this notification?", true)? {
+        send_charger_notification(charging_state, &PREVIEW_BATTERY_LEVEL, icon.as_deref().unwrap_or(""),
+            UrgencyLevel::Normal.into(), &preview_sound(&sound, base_dir), time);
+    }
+    Some(Some((icon, sound)))
+}
+
+/// Asks until the answer is a list of percentages, "none" is an empty list
+fn ask_thresholds(prompt: &mut impl Prompt, question: &str, default: &str) -> Option<Vec<u8>> {
+    loop {
+        let Some(answer) = ask_with_default(prompt, question, default)? else { return Some(Vec::new()) };
+        match parse_thresholds(&answer) {
+            Ok(thresholds) => return Some(thresholds),
+            Err(error) => prompt.say(&error),
+        }
+    }
+}
+
+/// Parses e.g. "20, 10" into sorted and deduplicated thresholds
+pub fn parse_thresholds(answer: &str) -> Result<Vec<u8>, String> {
+    let mut thresholds = answer
+        .split(|character: char| character == ',' || character.is_whitespace())
+        .filter(|threshold| !threshold.is_empty())
+        .map(|threshold| match threshold.trim_end_matches('%').parse::<u8>() {
+            Ok(percentage) if percentage <= 100 => Ok(percentage),
+            _ => Err(format!("\"{threshold}\" is not a percentage between 0 and 100")),
+        })
+        .collect::<Result<Vec<u8>, String>>()?;
+    thresholds.sort_unstable();
+    thresholds.dedup();
+    Ok(thresholds)
+}
+
+/// Asks until the answer is an existing file, an empty answer means no sound
+fn ask_sound(prompt: &mut impl Prompt, question: &str, base_dir: &Path) -> Option<Option<String>> {
+    loop {
+        let Some(sound) = ask_with_default(prompt, question, "none")? else { return Some(None) };
+        if Path::new(&expand_file_path(&sound, base_dir)).is_file() {
+            return Some(Some(sound));
+        }
+        prompt.say(&format!("{sound} was not found"));
+    }
+}
+
+/// Returns None if the input was closed, Some(None) if the answer was "none"
+fn ask_with_default(prompt: &mut impl Prompt, question: &str, default: &str) -> Option<Option<String>> {
+    let answer = prompt.ask(&format!("{question} [{default}]:"))?;
+    let answer = if answer.is_empty() { default.to_string() } else { answer };
+    if answer.eq_ignore_ascii_case("none") {
+        return Some(None);
+    }
+    Some(Some(answer))
+}
+
+fn ask_yes_no(prompt: &mut impl Prompt, question: &str, default: bool) -> Option<bool> {
+    let choices = if default { "Y/n" } else { "y/N" };
+    loop {
+        match prompt.ask(&format!("{question} [{choices}]"))?.to_lowercase().as_str() {
+            "" => return Some(default),
+            "y" | "yes" => return Some(true),
+            "n" | "no" => return Some(false),
+            _ => prompt.say("Please answer y or n"),
+        }
+    }
+}
+
+/// Sounds are written as entered, the preview needs them expanded like the config loader does
+fn preview_sound(sound: &Option<String>, base_dir: &Path) -> String {
+    sound.as_deref().map(|sound| expand_file_path(sound, base_dir)).unwrap_or_default()
+}
+
+pub fn stdin_prompt() -> TerminalPrompt<io::StdinLock<'static>, io::Stdout> {
+    TerminalPrompt::new(io::stdin().lock(), io::stdout())
+}
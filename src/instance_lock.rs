@@ -0,0 +1,145 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+    process,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::config_file_manager::xdg_dir_from_env;
+use crate::control_socket::{send_request, Request};
+use crate::state;
+
+const LOCK_FILE_NAME: &str = "spider-volt.lock";
+
+/// How long `--replace` waits for the running instance to exit
+pub const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// `$XDG_RUNTIME_DIR/spider-volt.lock`, falls back to the state dir so the guard also works without a runtime dir
+pub fn lock_path() -> Option<PathBuf> {
+    xdg_dir_from_env("XDG_RUNTIME_DIR")
+        .or_else(state::state_dir)
+        .map(|lock_dir| lock_dir.join(LOCK_FILE_NAME))
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    NoLockDir,
+    /// The pid is read from the lock file, None if it could not be read
+    AlreadyRunning(Option<u32>),
+    /// The running instance was asked to quit but still holds the lock
+    StillRunning(Option<u32>),
+    /// SIGTERM could not be sent to the running instance
+    Signal(u32, io::Error),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let instance = |pid: &Option<u32>| match pid {
+            Some(pid) => format!("spider-volt (pid {pid})"),
+            None => "spider-volt".to_string(),
+        };
+        match self {
+            LockError::NoLockDir => write!(formatter, "Could not get the runtime dir or the state dir for the lock file"),
+            LockError::AlreadyRunning(pid) => write!(formatter,
+                "{} is already running, every notification would be sent twice\n\
+                 Use --replace to stop it and start this instance instead", instance(pid)),
+            LockError::StillRunning(pid) => write!(formatter, "{} was asked to quit but is still running", instance(pid)),
+            LockError::Signal(pid, error) => match error.raw_os_error() {
+                Some(libc::ESRCH) => write!(formatter,
+                    "Could not stop spider-volt (pid {pid}), no process has that pid but the lock is still held"),
+                Some(libc::EPERM) => write!(formatter,
+                    "Not allowed to stop spider-volt (pid {pid}), it runs as another user"),
+                _ => write!(formatter, "Could not stop spider-volt (pid {pid})\n\t {error}"),
+            },
+            LockError::Io(path, error) => write!(formatter, "Could not lock {}\n\t {error}", path.display()),
+        }
+    }
+}
+
+/// Held by the running daemon, the kernel releases the lock when the process exits, even if it crashes
+/// The file is never removed, removing it would let two instances lock different files
+#[derive(Debug)]
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    pub fn acquire(path: &Path) -> Result<Self, LockError> {
+        let io_error = |error| LockError::Io(path.to_path_buf(), error);
+        if let Some(lock_dir) = path.parent() {
+            fs::create_dir_all(lock_dir).map_err(io_error)?;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false)
+            .open(path)
+            .map_err(io_error)?;
+
+        match file.try_lock() {
+            Ok(()) => (),
+            Err(TryLockError::WouldBlock) => return Err(LockError::AlreadyRunning(read_pid(&mut file))),
+            Err(TryLockError::Error(error)) => return Err(io_error(error)),
+        }
+        // The pid is only informational, it is shown to the next instance and used by --replace
+        file.set_len(0)
+            .and_then(|()| file.rewind())
+            .and_then(|()| writeln!(file, "{}", process::id()))
+            .map_err(io_error)?;
+        Ok(InstanceLock { _file: file })
+    }
+
+    /// Asks the running instance to quit and takes over its lock once it exited
+    /// The instance is asked through the control socket, or with SIGTERM if it has no socket
+    pub fn replace(path: &Path, socket_path: Option<&Path>, timeout: Duration) -> Result<Self, LockError> {
+        let pid = match InstanceLock::acquire(path) {
+            Err(LockError::AlreadyRunning(pid)) => pid,
+            lock => return lock,
+        };
+
+        let quit_requested = socket_path.is_some_and(|socket_path| send_request(socket_path, &Request::Quit).is_ok());
+        // Kept until the end, the instance may have exited between reading its pid and the signal
+        let mut missing_process = None;
+        if !quit_requested && let Some(pid) = pid {
+            match terminate(pid) {
+                Ok(()) => (),
+                Err(error) if error.raw_os_error() == Some(libc::ESRCH) => missing_process = Some(error),
+                Err(error) => return Err(LockError::Signal(pid, error)),
+            }
+        }
+
+        let started = Instant::now();
+        loop {
+            match InstanceLock::acquire(path) {
+                Err(LockError::AlreadyRunning(_)) if started.elapsed() < timeout => thread::sleep(Duration::from_millis(50)),
+                Err(LockError::AlreadyRunning(_)) => return Err(match (pid, missing_process) {
+                    (Some(pid), Some(error)) => LockError::Signal(pid, error),
+                    _ => LockError::StillRunning(pid),
+                }),
+                lock => return lock,
+            }
+        }
+    }
+}
+
+/// Sends SIGTERM to the running instance, used when it has no control socket to ask through
+/// pid 0 and our own pid are refused, signalling them would stop this process too
+fn terminate(pid: u32) -> io::Result<()> {
+    let target = libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|target| *target > 0 && pid != process::id())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{pid} is not the pid of another process")))?;
+    // SAFETY: kill only sends a signal, it has no memory safety requirements
+    if unsafe { libc::kill(target, libc::SIGTERM) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
@@ -0,0 +1,222 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, Write},
+    os::{fd::AsFd, unix::fs::MetadataExt},
+    sync::OnceLock,
+};
+
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+// ----------------------------------------------------------------
+// Log Formats
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Info messages as they are, other levels prefixed with the level, e.g. "warning: ..."
+    Text,
+    /// Every line prefixed with its syslog priority, e.g. "<4>", which journald reads from stderr
+    Journal,
+    /// One JSON object per line with timestamp, level, target and message
+    Json,
+}
+
+impl LogFormat {
+    pub const NAMES: [&'static str; 3] = ["text", "journal", "json"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "text" => Some(LogFormat::Text),
+            "journal" => Some(LogFormat::Journal),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// Journal if systemd connected stderr to the journal, which it announces with `$JOURNAL_STREAM`
+    /// The variable is inherited by child processes whose stderr may be redirected,
+    /// so stderr has to be the stream it names, as systemd.exec(5) recommends
+    pub fn detect() -> Self {
+        let connected_to_journal = env::var("JOURNAL_STREAM")
+            .ok()
+            .zip(stderr_identity())
+            .is_some_and(|(journal_stream, (device, inode))| is_journal_stream(&journal_stream, device, inode));
+        if connected_to_journal {
+            LogFormat::Journal
+        } else {
+            LogFormat::Text
+        }
+    }
+}
+
+/// The device and inode numbers of stderr
+fn stderr_identity() -> Option<(u64, u64)> {
+    let stderr = File::from(io::stderr().as_fd().try_clone_to_owned().ok()?);
+    let metadata = stderr.metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Whether `$JOURNAL_STREAM`, formatted as "device:inode", names the given file
+pub fn is_journal_stream(journal_stream: &str, device: u64, inode: u64) -> bool {
+    let Some((stream_device, stream_inode)) = journal_stream.split_once(':') else { return false };
+    stream_device.parse() == Ok(device) && stream_inode.parse() == Ok(inode)
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    message: &'a str,
+}
+
+/// The lines written for one log record, without the trailing newline
+pub fn format_record(format: LogFormat, level: Level, target: &str, message: &str, time: DateTime<Local>) -> String {
+    match format {
+        LogFormat::Text => match level {
+            Level::Info => message.to_string(),
+            Level::Warn => format!("warning: {message}"),
+            level => format!("{}: {message}", level.as_str().to_lowercase()),
+        },
+        // journald reads every line as its own entry, so every line needs the prefix
+        LogFormat::Journal => message
+            .lines()
+            .map(|line| format!("<{}>{line}", syslog_priority(level)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        LogFormat::Json => {
+            let level = level.as_str().to_lowercase();
+            let json_line = JsonLine { timestamp: time.to_rfc3339(), level: &level, target, message };
+            serde_json::to_string(&json_line).unwrap_or_default()
+        },
+    }
+}
+
+/// sd-daemon(3) priorities, trace has no priority of its own and is logged as debug
+fn syslog_priority(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+// ----------------------------------------------------------------
+// Log Filter, the RUST_LOG syntax without regex filters
+//   RUST_LOG=debug
+//   RUST_LOG=warn,spider_volt::audio=trace
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    default: LevelFilter,
+    /// (module path, level), the longest matching module path is used
+    directives: Vec<(String, LevelFilter)>,
+}
+
+impl LogFilter {
+    pub fn new(default: LevelFilter) -> Self {
+        LogFilter { default, directives: Vec::new() }
+    }
+
+    /// Directives that cannot be parsed are skipped and returned as errors
+    pub fn parse(spec: &str, default: LevelFilter) -> (Self, Vec<String>) {
+        let mut filter = LogFilter::new(default);
+        let mut errors = Vec::new();
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            let (target, level) = match directive.split_once('=') {
+                Some((target, level)) => (Some(target.trim()), level.trim()),
+                // A bare name is a level, or a module logged at every level like in env_logger
+                None if directive.parse::<LevelFilter>().is_err() => (Some(directive), "trace"),
+                None => (None, directive),
+            };
+            match (target, level.parse::<LevelFilter>()) {
+                (None, Ok(level)) => filter.default = level,
+                (Some(target), Ok(level)) => filter.directives.push((target.to_string(), level)),
+                (_, Err(_)) => errors.push(format!("Ignoring the log directive \"{directive}\", {level:?} is not a log level")),
+            }
+        }
+        (filter, errors)
+    }
+
+    /// -v and -q replace the level of RUST_LOG, module directives are kept
+    pub fn with_default(mut self, default: LevelFilter) -> Self {
+        self.default = default;
+        self
+    }
+
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .filter(|(module_path, _)| {
+                target == module_path || target.strip_prefix(module_path.as_str()).is_some_and(|rest| rest.starts_with("::"))
+            })
+            .max_by_key(|(module_path, _)| module_path.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+}
+
+/// Info by default, every -v shows one more level and every -q one less
+pub fn verbosity_level(verbose: u8, quiet: u8) -> LevelFilter {
+    match i16::from(verbose) - i16::from(quiet) {
+        ..=-3 => LevelFilter::Off,
+        -2 => LevelFilter::Error,
+        -1 => LevelFilter::Warn,
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        2.. => LevelFilter::Trace,
+    }
+}
+
+// ----------------------------------------------------------------
+// Logger, everything is written to stderr so stdout only has the output of commands
+// ----------------------------------------------------------------
+struct Logger {
+    filter: LogFilter,
+    format: LogFormat,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.filter.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format_record(self.format, record.level(), record.target(), &record.args().to_string(), Local::now());
+        let _ = writeln!(io::stderr().lock(), "{line}");
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+/// Sets up logging for the whole process, `verbosity` is the level from -v and -q if either was passed
+/// `format` is detected if not passed, the journal format is used under systemd
+pub fn init_logging(verbosity: Option<LevelFilter>, format: Option<LogFormat>) {
+    let (mut filter, errors) = match env::var("RUST_LOG") {
+        Ok(spec) => LogFilter::parse(&spec, LevelFilter::Info),
+        Err(_) => (LogFilter::new(LevelFilter::Info), Vec::new()),
+    };
+    if let Some(verbosity) = verbosity {
+        filter = filter.with_default(verbosity);
+    }
+
+    let max_level = filter.max_level();
+    let logger = LOGGER.get_or_init(|| Logger { filter, format: format.unwrap_or_else(LogFormat::detect) });
+    if log::set_logger(logger).is_ok() {
+        log::set_max_level(max_level);
+    }
+    errors.iter().for_each(|error| log::warn!("{error}"));
+}
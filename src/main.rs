@@ -1,61 +1,295 @@
 use std::collections::HashSet;
-use std::{thread, time::Duration};
+use std::{env, fs, path::PathBuf, process, thread, time::Duration};
 
-use crate::cli_args::{parse_cli};
-use crate::config_file_manager::{create_default_config_file, handle_config_file, print_config_toml_with_message};
+use chrono::{DateTime, Local, TimeDelta};
+use log::{debug, error, info, trace, warn};
+
+use crate::bar::run_bar;
+use crate::cli_args::{parse_cli, CliCommand, ConfigCommand};
+use crate::config_editor::{config_get, config_set, config_unset};
+use crate::config_file_manager::{check_config_file, config_file_chain, config_sources, create_default_config_file, handle_config_file, load_config_chain, print_config_schema, print_config_with_message, ConfigFormat};
+use crate::config_origins::{annotate_config_origins, config_origins};
+use crate::config_overrides::config_overrides;
+use crate::config_reloader::ConfigReloader;
+use crate::config_template::config_template;
+use crate::control_socket::{request_profile_switch, request_reload, ControlConnection, ControlError, ControlSocket, Request, Response};
+use crate::events::{print_events, DaemonEvent};
+use crate::init_wizard::{run_init_wizard, stdin_prompt};
+use crate::instance_lock::{InstanceLock, LockError, REPLACE_TIMEOUT};
+use crate::logging::init_logging;
+use crate::profile_rules::{announce_profile_switch, ProfileSelector, SystemClock};
+use crate::service::run_service_command;
+use crate::shutdown::{finish_notifications, Signals};
+use crate::status::{battery_status, format_status, print_status, StatusReport, StatusSource};
+use crate::systemd::SystemdNotifier;
 
 extern crate battery;
+mod bar;
 mod config;
+mod config_editor;
 mod config_file_manager;
+mod config_origins;
+mod config_overrides;
+mod config_reloader;
+mod config_template;
+mod control_socket;
+mod events;
+mod init_wizard;
+mod instance_lock;
 mod cli_args;
 mod battery_monitor;
+mod logging;
 mod notifications;
 mod audio;
+mod path_expansion;
+mod profile_rules;
+mod service;
+mod shutdown;
+mod state;
+mod status;
+mod systemd;
 mod tests;
 
 /*
  * spider-volt [flags] [args]
+ * spider-volt init [--force] asks a few questions and creates the config file
+ * spider-volt config get|set|unset <key> [value] edits the config file, keeping its comments
+ * spider-volt status [--json] [--standalone] prints what the running daemon is doing
+ * spider-volt watch streams the events of the running daemon as JSON lines
+ * spider-volt bar [--mode waybar|i3blocks|polybar] [--standalone] keeps printing the status for a status bar
+ * spider-volt service install [--enable]|uninstall|print manages the systemd user unit
+ *
  * -h, --help
  * -V, --version
  *
  * -c, --config <file>
  *   , --print-config
+ *   , --show-origin annotates --print-config with the file each value came from
  *   , --print-config-template
- *   , --create-config creates a config template to ~/.config/spider-volt
+ *   , --format <toml|json|yaml> format used by the two flags above
+ *   , --print-schema prints a JSON Schema of the config
+ *   , --create-config creates a config template to $XDG_CONFIG_HOME/spider-volt
+ *   , --force overwrites the config file with --create-config, keeping a .bak backup
+ *   , --check-config checks the config file and exits non-zero on errors
+ *   , --low <threshold:message> and --high <threshold:message> add or override notifications
+ * -p, --profile <name> uses a profile from the config file
+ *   , --set-profile <name> switches the running daemon to a profile
+ *   , --replace stops the running daemon and takes its place
+ * -v, --verbose and -q, --quiet log more or less, can be repeated
+ *   , --log-format <text|journal|json> format of the log on stderr
  *
  * if no flags are passed 
- *      the config file in ~/.config/spider-volt is used
+ *      the config file in $XDG_CONFIG_HOME/spider-volt (~/.config/spider-volt) is used
+ *      layered on top of /etc/spider-volt/config.toml and $XDG_CONFIG_DIRS/spider-volt/config.toml
+ *      every config file is layered after the files it includes and before the files in config.d next to it
  * if no config file is found
  *       a default config is used
  *
+ * SPIDER_VOLT_* environment variables and --low/--high are layered on top of the config files
+ * the config is reloaded when the file changes or on SIGHUP
+ * profile rules in the config switch the profile automatically
+ * only one daemon runs at a time, guarded by a lock on $XDG_RUNTIME_DIR/spider-volt.lock
+ * the running daemon is controlled through $XDG_RUNTIME_DIR/spider-volt.sock, see control_socket.rs
+ * SIGTERM and SIGINT stop the daemon after the last sound played, SIGUSR1 logs the status
+ * thresholds that already fired are saved in $XDG_STATE_HOME/spider-volt and not sent again after a restart
+ * under a Type=notify systemd unit the daemon reports readiness, its status and watchdog pings
+ * messages are logged to stderr, RUST_LOG filters them by module, see logging.rs
+ *
 */
 fn main() {
     let cli_arguments = parse_cli();   
+    init_logging(cli_arguments.verbosity, cli_arguments.log_format);
+
+    match &cli_arguments.command {
+        Some(CliCommand::Init { force }) => {
+            if !run_init_wizard(&mut stdin_prompt(), &cli_arguments.config_path, *force) {
+                process::exit(1);
+            }
+            return;
+        },
+        Some(CliCommand::Config(config_command)) => {
+            let config_path = &cli_arguments.config_path;
+            let result = match config_command {
+                ConfigCommand::Get { key } => config_get(config_path, key).map(|value| println!("{value}")),
+                ConfigCommand::Set { key, value } => config_set(config_path, key, value),
+                ConfigCommand::Unset { key } => config_unset(config_path, key),
+            };
+            if let Err(error) = result {
+                error!("{error}");
+                process::exit(1);
+            }
+            // The daemon would also notice the renamed file, asking makes sure the edit is applied when this exits
+            if !matches!(config_command, ConfigCommand::Get { .. })
+                && let Err(error) = request_reload() {
+                error!("{error}");
+            }
+            return;
+        },
+        Some(CliCommand::Status { json, standalone }) => {
+            if !print_status(*json, *standalone) {
+                process::exit(1);
+            }
+            return;
+        },
+        Some(CliCommand::Watch) => {
+            if !print_events() {
+                process::exit(1);
+            }
+            return;
+        },
+        Some(CliCommand::Service(service_command)) => {
+            if let Err(error) = run_service_command(service_command) {
+                error!("{error}");
+                process::exit(1);
+            }
+            return;
+        },
+        // Needs the config files and overrides, handled once they are known
+        Some(CliCommand::Bar { .. }) | None => (),
+    }
     
     if cli_arguments.print_config_template {
-        print_config_toml_with_message(&config::Config::default(),
-            "Printing Config File Template").ok();
+        info!("Printing Config File Template\n");
+        match config_template(cli_arguments.format) {
+            Ok(template) => println!("{template}"),
+            Err(error) => error!("{error}"),
+        }
+        return;
+    }
+
+    if cli_arguments.print_schema {
+        if let Err(error) = print_config_schema() {
+            error!("{error}");
+        }
         return;
     }
     
     if cli_arguments.create_config {
-        create_default_config_file(&cli_arguments.config_path);
+        create_default_config_file(&cli_arguments.config_path, None, cli_arguments.force);
         return;
     }
     
-    let configuration = handle_config_file(&cli_arguments.config_path);
+    if cli_arguments.check_config {
+        if !check_config_file(&cli_arguments.config_path) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let config_files = config_file_chain(&cli_arguments.config_path);
+    if let Some(profile) = &cli_arguments.set_profile {
+        // The running daemon checks the profile against its own config and saves it
+        match request_profile_switch(profile) {
+            Ok(true) => {
+                println!("Switched the running daemon to the profile \"{profile}\"");
+                return;
+            },
+            Ok(false) => (),
+            Err(error) => {
+                error!("{error}");
+                process::exit(1);
+            }
+        }
+        let configuration = handle_config_file(&config_files, None, None);
+        if let Err(error) = configuration.check_profile_name(profile) {
+            error!("{error}");
+            process::exit(1);
+        }
+        match state::save_active_profile(profile) {
+            Ok(()) => println!("Switched to the profile \"{profile}\""),
+            Err(error) => {
+                error!("Could not save the active profile\n\t {error}");
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let overrides = match config_overrides(env::vars_os(), &cli_arguments.low_overrides, &cli_arguments.high_overrides) {
+        Ok(overrides) => overrides,
+        Err(error) => {
+            error!("{error}");
+            process::exit(1);
+        }
+    };
+    // The bar follows the daemon's profile, so it loads the config itself
+    if let Some(CliCommand::Bar { mode, standalone, interval }) = cli_arguments.command {
+        if !run_bar(mode, standalone, interval, &config_files, overrides.as_ref()) {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let active_profile = cli_arguments.profile.clone().or_else(state::load_active_profile);
+    let mut configuration = handle_config_file(&config_files, overrides.as_ref(), active_profile.as_deref());
+    // A typo would otherwise be saved below and applied silently on every restart,
+    // a saved profile that was removed from the config since is only warned about while loading
+    if let Some(profile) = &cli_arguments.profile
+        && let Err(error) = configuration.check_profile_name(profile) {
+        error!("{error}");
+        process::exit(1);
+    }
+
+    if cli_arguments.print_config && cli_arguments.show_origin {
+        if cli_arguments.format != ConfigFormat::Toml {
+            error!("--show-origin only works with --format toml");
+            process::exit(1);
+        }
+        let annotated_config = config_origins(&config_files, overrides.as_ref(), &configuration)
+            .map_err(|error| error.to_string())
+            .and_then(|origins| annotate_config_origins(&configuration, &origins));
+        match annotated_config {
+            Ok(annotated_config) => {
+                info!("Printing Current Config File\n");
+                println!("{annotated_config}");
+            },
+            Err(error) => error!("{error}"),
+        }
+        return;
+    }
 
     if cli_arguments.print_config {
-        print_config_toml_with_message(&configuration,
-            "Printing Current Config File").ok();
+        if let Err(error) = print_config_with_message(&configuration,
+            "Printing Current Config File", cli_arguments.format) {
+            error!("{error}");
+        }
         return;
     }
 
+    // A second instance would send every notification twice, --replace stops the running one instead
+    let lock = instance_lock::lock_path().ok_or(LockError::NoLockDir).and_then(|lock_path| {
+        if cli_arguments.replace {
+            InstanceLock::replace(&lock_path, control_socket::socket_path().as_deref(), REPLACE_TIMEOUT)
+        } else {
+            InstanceLock::acquire(&lock_path)
+        }
+    });
+    let _instance_lock = match lock {
+        Ok(instance_lock) => instance_lock,
+        Err(error) => {
+            error!("{error}");
+            process::exit(1);
+        }
+    };
+
+    // The profile passed with --profile is kept for the next restarts
+    if let Some(profile) = &cli_arguments.profile
+        && let Err(error) = state::save_active_profile(profile) {
+        warn!("Could not save the active profile\n\t {error}");
+    }
+
     let mut battery_notif_sent: HashSet<u8> = HashSet::new();
-    let notif_time = configuration.time();
-    let charger_notif = &configuration.charger_notifications;
-    let low_level_notifs = &configuration.low_battery_levels;
-    let high_level_notifs = &configuration.high_battery_levels;
+    // A directory that does not exist cannot be watched, so it is created before the active profile file is watched
+    if let Some(state_dir) = state::state_dir()
+        && let Err(error) = fs::create_dir_all(&state_dir) {
+        warn!("Could not create {}\n\t {error}", state_dir.display());
+    }
+    // Switching profiles at runtime writes to the active profile file, so it is watched like a config file
+    let files_to_watch: Vec<PathBuf> = config_files.iter().cloned().chain(state::active_profile_path()).collect();
+    let mut config_reloader = ConfigReloader::new(&files_to_watch);
+    // Included files and drop-ins are watched too, and watched again after every reload since they can change
+    let sources = config_sources(&config_files);
+    config_reloader.watch(&sources.files, &sources.directories);
 
     let manager = match battery_monitor::init_battery_manager() {
         Some(manager) => manager,
@@ -71,13 +305,235 @@ fn main() {
         Some(battery_stats) => battery_stats,
         None => return,
     };
+
+    // Thresholds that fired before a restart or crash are not notified again, if the battery did not change in between
+    let notification_state_path = state::notification_state_path();
+    let mut saved_notification_state = match notification_state_path.as_deref().map(state::load_notification_state) {
+        Some(Ok(saved_notification_state)) => saved_notification_state,
+        Some(Err(error)) => {
+            warn!("{error}\nThresholds that already fired may be notified again");
+            None
+        },
+        None => None,
+    };
+    if let Some(saved) = saved_notification_state.clone() {
+        match battery_stats.restore_notification_state(saved, &mut battery_notif_sent) {
+            Ok(()) => configuration.retain_sent_thresholds(&mut battery_notif_sent),
+            Err(reason) => info!("Discarded the saved notification state, {reason}"),
+        }
+    }
     
-    loop {
+    // The manual profile comes from --profile or --set-profile, rules take priority over it
+    let mut manual_profile = active_profile;
+    let mut applied_profile = manual_profile.clone();
+    let mut profile_selector = ProfileSelector::new(SystemClock);
+
+    // Status bars and keybindings talk to the daemon through this socket, the daemon works without it
+    let mut control_socket = match control_socket::socket_path()
+        .ok_or(ControlError::NoRuntimeDir)
+        .and_then(|socket_path| ControlSocket::bind(&socket_path)) {
+        Ok(control_socket) => Some(control_socket),
+        Err(error) => {
+            warn!("{error}\nRunning without the control socket");
+            None
+        }
+    };
+    let mut snoozed_until: Option<DateTime<Local>> = None;
+    // Connections of `spider-volt watch`, dropped once they stop reading
+    let mut watchers: Vec<ControlConnection> = Vec::new();
+    let signals = Signals::register();
+
+    // Under a Type=notify unit systemd waits for READY=1 and restarts the daemon if the watchdog pings stop
+    let mut systemd = SystemdNotifier::from_env();
+    if let Some(systemd) = systemd.as_mut() {
+        let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+        systemd.ready(&battery_status(&battery_stats, profile_name, &battery_notif_sent, snoozed_until));
+    }
+
+    let stop_reason = 'daemon: loop {
+            if let Some(signal) = signals.shutdown_requested() {
+                break format!("Received {signal}");
+            }
             battery_stats.update_battery_stats(&manager, &mut battery);
-            // println!("{:?}", battery_stats);
+            profile_selector.record_charger_state(battery_stats.is_charging());
+            if snoozed_until.is_some_and(|until| until <= Local::now()) {
+                snoozed_until = None;
+            }
+
+            let mut reload_requested = config_reloader.reload_requested();
+            if reload_requested {
+                manual_profile = state::load_active_profile();
+            }
+
+            // Reloads are answered once the new config is loaded, every other request right away
+            let mut reload_connections = Vec::new();
+            let mut events = Vec::new();
+            let mut requests = control_socket.iter_mut().flat_map(ControlSocket::accept);
+            while let Some((request, connection)) = requests.next() {
+                match request {
+                    Request::Status => {
+                        let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+                        connection.reply(Response::status(
+                            battery_status(&battery_stats, profile_name, &battery_notif_sent, snoozed_until)));
+                    },
+                    Request::Reload => {
+                        reload_requested = true;
+                        reload_connections.push(connection);
+                    },
+                    Request::Snooze { minutes: 0 } => {
+                        snoozed_until = None;
+                        connection.reply(Response::ok());
+                    },
+                    Request::Snooze { minutes } => {
+                        match i64::try_from(minutes).ok()
+                            .and_then(TimeDelta::try_minutes)
+                            .and_then(|snooze| Local::now().checked_add_signed(snooze)) {
+                            Some(until) => {
+                                snoozed_until = Some(until);
+                                connection.reply(Response::ok());
+                            },
+                            None => connection.reply(Response::error(format!("Cannot snooze for {minutes} minutes"))),
+                        }
+                    },
+                    Request::SwitchProfile { profile } => {
+                        if let Err(error) = configuration.check_profile_name(&profile) {
+                            connection.reply(Response::error(error));
+                            continue;
+                        }
+                        // Saved like --set-profile, so the profile is kept across restarts
+                        match state::save_active_profile(&profile) {
+                            Ok(()) => {
+                                manual_profile = Some(profile).filter(|profile| profile != config::DEFAULT_PROFILE);
+                                connection.reply(Response::ok());
+                            },
+                            Err(error) => connection.reply(Response::error(format!("Could not save the active profile: {error}"))),
+                        }
+                    },
+                    Request::TestNotification => {
+                        battery_stats.send_test_notification(&configuration.low_battery_levels, configuration.time());
+                        connection.reply(Response::ok());
+                    },
+                    Request::Watch => {
+                        let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+                        let status = battery_status(&battery_stats, profile_name, &battery_notif_sent, snoozed_until);
+                        let mut watcher = connection;
+                        if watcher.send(&Response::ok()) && watcher.send(&DaemonEvent::Status(status)) {
+                            watchers.push(watcher);
+                        }
+                    },
+                    Request::Quit => {
+                        connection.reply(Response::ok());
+                        // Every other client of this batch would otherwise only see the connection close
+                        let unanswered = reload_connections.into_iter().chain(requests.map(|(_, connection)| connection));
+                        for connection in unanswered {
+                            connection.reply(Response::error("The daemon is shutting down"));
+                        }
+                        break 'daemon "Quit requested through the control socket".to_string();
+                    },
+                }
+            }
+            let matching_rule = profile_selector.matching_rule(configuration.profile_rules());
+            let wanted_profile = match matching_rule.map(|(_, rule)| rule.profile.as_str()) {
+                Some(profile) if profile == config::DEFAULT_PROFILE => None,
+                Some(profile) => Some(profile.to_string()),
+                None => manual_profile.clone(),
+            };
+            let profile_changed = wanted_profile != applied_profile;
+            if profile_changed {
+                let old_profile = applied_profile.as_deref().unwrap_or(config::DEFAULT_PROFILE);
+                let new_profile = wanted_profile.as_deref().unwrap_or(config::DEFAULT_PROFILE);
+                match matching_rule {
+                    Some((index, rule)) => info!(
+                        "Switching from the profile {old_profile} to {new_profile}, profile_rules.{index} matched ({rule})"),
+                    None => info!("Switching from the profile {old_profile} to {new_profile}, selected manually since no profile rule matches"),
+                }
+            }
+
+            if reload_requested || profile_changed {
+                // The new config replaces the old one as a whole, between two iterations
+                let reload_response = match load_config_chain(&config_files, overrides.as_ref(), wanted_profile.as_deref()) {
+                    Ok(new_configuration) => {
+                        new_configuration.retain_sent_thresholds(&mut battery_notif_sent);
+                        configuration = new_configuration;
+                        let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+                        info!("Reloaded the config, active profile: {profile_name}");
+                        if profile_changed && configuration.announce_profile_switches() {
+                            announce_profile_switch(profile_name, configuration.time());
+                        }
+                        events.push(DaemonEvent::ConfigReloaded { profile: profile_name.to_string(), error: None });
+                        Response::ok()
+                    },
+                    Err(error) => {
+                        error!("{error}\nKeeping the current config");
+                        let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+                        events.push(DaemonEvent::ConfigReloaded { profile: profile_name.to_string(), error: Some(error.to_string()) });
+                        Response::error(format!("{error}\nKeeping the current config"))
+                    },
+                };
+                reload_connections.into_iter().for_each(|connection| connection.reply(reload_response.clone()));
+                // Even if loading failed, so a broken config is not reloaded every second
+                applied_profile = wanted_profile;
+                let sources = config_sources(&config_files);
+                config_reloader.watch(&sources.files, &sources.directories);
+            }
+
+            let notif_time = configuration.time();
+            // While snoozed the charger state is still tracked, so a change during the snooze is not announced after it
+            let charger_notif = if snoozed_until.is_some() { &None } else { &configuration.charger_notifications };
+            let low_level_notifs = &configuration.low_battery_levels;
+            let high_level_notifs = &configuration.high_battery_levels;
+
+            trace!("{battery_stats:?}");
             battery_stats.handle_charger_notifications(charger_notif, notif_time);
             battery_stats.handle_battery_state_change(&mut battery_notif_sent);
-            battery_stats.handle_battery(low_level_notifs, high_level_notifs, notif_time, &mut battery_notif_sent);
+            // Thresholds reached while snoozed are sent once the snooze ends, if they still apply
+            if snoozed_until.is_none() {
+                battery_stats.handle_battery(low_level_notifs, high_level_notifs, notif_time, &mut battery_notif_sent);
+            }
+
+            if signals.status_requested() {
+                let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+                let status = battery_status(&battery_stats, profile_name, &battery_notif_sent, snoozed_until);
+                info!("Status requested with SIGUSR1\n{}", format_status(&StatusReport { source: StatusSource::Daemon, status }).trim_end());
+            }
+
+            events.extend(battery_stats.take_events());
+            for event in &events {
+                debug!("{}", serde_json::to_string(event).unwrap_or_default());
+            }
+            for event in &events {
+                watchers.retain_mut(|watcher| watcher.send(event));
+            }
+            // Saved whenever it changes, so it survives a crash too
+            let notification_state = battery_stats.notification_state(&battery_notif_sent);
+            if saved_notification_state.as_ref() != Some(&notification_state) {
+                if let Some(path) = &notification_state_path
+                    && let Err(error) = state::save_notification_state(path, &notification_state) {
+                    warn!("Could not save the notification state\n\t {error}");
+                }
+                saved_notification_state = Some(notification_state);
+            }
+            if let Some(systemd) = systemd.as_mut() {
+                let profile_name = configuration.active_profile().unwrap_or(config::DEFAULT_PROFILE);
+                systemd.status(&battery_status(&battery_stats, profile_name, &battery_notif_sent, snoozed_until));
+                systemd.watchdog();
+            }
             thread::sleep(Duration::from_secs(1));
+    };
+
+    info!("{stop_reason}, shutting down");
+    if let Some(systemd) = systemd.as_mut() {
+        systemd.stopping();
+    }
+    // `spider-volt watch` sees the connection close and exits
+    drop(watchers);
+    if let Err(error) = state::save_active_profile(manual_profile.as_deref().unwrap_or(config::DEFAULT_PROFILE)) {
+        warn!("Could not save the active profile\n\t {error}");
+    }
+    if let Some(path) = &notification_state_path
+        && let Err(error) = state::save_notification_state(path, &battery_stats.notification_state(&battery_notif_sent)) {
+        warn!("Could not save the notification state\n\t {error}");
     }
-}
\ No newline at end of file
+    finish_notifications();
+    // The control socket and the instance lock are released when they are dropped
+}
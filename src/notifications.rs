@@ -1,7 +1,26 @@
 #![cfg(not(test))]
-use notify_rust::{Hint, Notification, Urgency};
+use std::sync::Mutex;
+
+use log::error;
+use notify_rust::{Hint, Notification, NotificationHandle, Urgency};
 use crate::audio::play_notification_sound;
 
+/// Every notification replaces the previous one, so only the last one can still be on screen
+static LAST_NOTIFICATION: Mutex<Option<NotificationHandle>> = Mutex::new(None);
+
+fn remember_notification(notification: NotificationHandle) {
+    if let Ok(mut last_notification) = LAST_NOTIFICATION.lock() {
+        *last_notification = Some(notification);
+    }
+}
+
+/// Closes the last notification on shutdown, notifications that never expire would otherwise outlive the daemon
+pub fn close_last_notification() {
+    if let Some(notification) = LAST_NOTIFICATION.lock().ok().and_then(|mut last_notification| last_notification.take()) {
+        notification.close();
+    }
+}
+
 
 pub fn send_battery_notification(
         battery_level: &i32, 
@@ -12,7 +31,7 @@ pub fn send_battery_notification(
         time: i32
     ){
 
-    Notification::new()
+    let notification = Notification::new()
     .hint(Hint::Transient(true))
     .hint(Hint::Custom("synchronous".into(), "battery_notif".into()))  
 
@@ -24,6 +43,7 @@ pub fn send_battery_notification(
 
     .show()
     .unwrap();
+    remember_notification(notification);
     
     play_notification_sound(notif_sound);
 }
@@ -37,7 +57,7 @@ pub fn send_charger_notification(
         time: i32
     ) {
     
-    Notification::new()
+    let notification = Notification::new()
     
     // Transient hint means the notification by-passes the server's persistence and is not stored 
     .hint(Hint::Transient(true))
@@ -53,8 +73,30 @@ pub fn send_charger_notification(
 
     .show()
     .unwrap();
+    remember_notification(notification);
     
     play_notification_sound(notif_sound);
-} 
+}
+
+pub fn send_profile_notification(
+        profile: &str,
+        notif_urgency: Urgency,
+        time: i32
+    ) {
+
+    let notification = Notification::new()
+    .hint(Hint::Transient(true))
+    .hint(Hint::Custom("synchronous".into(), "battery_notif".into()))
 
+    .summary("Profile Switched")
+    .body(&format!("Switched to the {profile} profile"))
+    .urgency(notif_urgency)
+    .timeout(time)
 
+    .show();
+    // The profile is switched either way, a missing notification server only loses the announcement
+    match notification {
+        Ok(notification) => remember_notification(notification),
+        Err(error) => error!("Could not announce the switch to the {profile} profile\n\t {error}"),
+    }
+}
@@ -0,0 +1,155 @@
+use std::{env, path::Path};
+
+use log::warn;
+
+// ----------------------------------------------------------------
+// Trait for structs that carry paths in the config file
+// ----------------------------------------------------------------
+/// Implemented by every config struct that has path fields,
+/// new path fields only need to be added to the struct's implementation
+pub trait ExpandPaths {
+    /// Expands `~` and environment variables in every path field,
+    /// relative paths are resolved against `base_dir` (the config file's directory)
+    fn expand_paths(&mut self, base_dir: &Path);
+}
+
+/// Expands a path to a file, relative paths are always resolved against `base_dir`
+/// Empty strings are left untouched since they mean "no file"
+pub fn expand_file_path(raw_path: &str, base_dir: &Path) -> String {
+    if raw_path.trim().is_empty() {
+        return raw_path.to_string();
+    }
+    let (expanded, unset_variables) = expand_variables(&expand_tilde(raw_path), |name| env::var(name).ok());
+    // the path can never exist with a variable left in it, joining it onto base_dir would only hide that
+    if !unset_variables.is_empty() {
+        let verb = if unset_variables.len() == 1 { "is" } else { "are" };
+        warn!("{raw_path}: {} {verb} not set, the path is left as is", unset_variables.join(", "));
+        return expanded;
+    }
+    let path = Path::new(&expanded);
+    if path.is_relative() {
+        return base_dir.join(path).display().to_string();
+    }
+    expanded
+}
+
+/// Expands a notification icon
+/// Icons can either be a path or an icon name (e.g. "battery-alert") from the icon theme,
+/// so only values that look like paths are expanded
+pub fn expand_icon(raw_icon: &str, base_dir: &Path) -> String {
+    let looks_like_path = raw_icon.starts_with('~')
+        || raw_icon.starts_with('$')
+        || raw_icon.starts_with('.')
+        || raw_icon.contains('/');
+
+    if looks_like_path {
+        expand_file_path(raw_icon, base_dir)
+    } else {
+        raw_icon.to_string()
+    }
+}
+
+/// Helper to expand an optional path field in place
+pub fn expand_optional(field: &mut Option<String>, base_dir: &Path, expand: fn(&str, &Path) -> String) {
+    if let Some(value) = field.as_mut() {
+        *value = expand(value, base_dir);
+    }
+}
+
+/// Replaces a leading `~` or `~/` with the home directory
+fn expand_tilde(raw_path: &str) -> String {
+    let rest = match raw_path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        // `~user/...` is not supported, keep it as is
+        _ => return raw_path.to_string(),
+    };
+    match env::home_dir() {
+        Some(home_directory) => format!("{}{rest}", home_directory.display()),
+        None => {
+            warn!("Could not get home dir to expand {raw_path}");
+            raw_path.to_string()
+        }
+    }
+}
+
+/// The XDG base directories and the defaults the spec gives them when they are unset
+const XDG_BASE_DIRS: [(&str, &str); 4] = [
+    ("XDG_CONFIG_HOME", "~/.config"),
+    ("XDG_DATA_HOME", "~/.local/share"),
+    ("XDG_STATE_HOME", "~/.local/state"),
+    ("XDG_CACHE_HOME", "~/.cache"),
+];
+
+/// The value of a variable, empty values count as unset in every form
+/// XDG base directories have to be absolute, like in `xdg_dir_from_env`
+fn variable_value(name: &str, lookup: &impl Fn(&str) -> Option<String>) -> Option<String> {
+    let value = lookup(name).filter(|value| !value.is_empty())?;
+    let is_xdg_base_dir = XDG_BASE_DIRS.iter().any(|(variable, _)| *variable == name);
+    if is_xdg_base_dir && !Path::new(&value).is_absolute() {
+        return None;
+    }
+    Some(value)
+}
+
+/// The default of an unset XDG base directory, e.g. `~/.local/share` for `$XDG_DATA_HOME`
+fn xdg_default(name: &str) -> Option<String> {
+    XDG_BASE_DIRS
+        .iter()
+        .find(|(variable, _)| *variable == name)
+        .map(|(_, default)| expand_tilde(default))
+}
+
+/// Expands `$VAR`, `${VAR}` and `${VAR:-default}`, reading variables through `lookup`
+/// Unset XDG base directories fall back to their default from the spec, unless a default is given
+/// Other unset variables without a default are left untouched so the error is visible when the file is opened,
+/// they are returned with the expanded path, e.g. `["$SOUNDS"]`
+pub fn expand_variables(raw_path: &str, lookup: impl Fn(&str) -> Option<String>) -> (String, Vec<String>) {
+    let mut expanded = String::with_capacity(raw_path.len());
+    let mut unset_variables = Vec::new();
+    let mut rest = raw_path;
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after_dollar = &rest[dollar + 1..];
+
+        // ${VAR} or ${VAR:-default}
+        if let Some(braced) = after_dollar.strip_prefix('{')
+            && let Some(close) = braced.find('}') {
+            let inner = &braced[..close];
+            let (name, default) = match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(expand_tilde(default))),
+                None => (inner, None),
+            };
+            match variable_value(name, &lookup).or(default).or_else(|| xdg_default(name)) {
+                Some(value) => expanded.push_str(&value),
+                None => {
+                    let variable = &rest[dollar..dollar + close + 3];
+                    expanded.push_str(variable);
+                    unset_variables.push(variable.to_string());
+                },
+            }
+            rest = &braced[close + 1..];
+            continue;
+        }
+
+        // $VAR
+        let name_length = after_dollar
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after_dollar.len());
+        let name = &after_dollar[..name_length];
+        match variable_value(name, &lookup).or_else(|| xdg_default(name)) {
+            Some(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => {
+                expanded.push('$');
+                expanded.push_str(name);
+                // a `$` without a name is only a character
+                if !name.is_empty() {
+                    unset_variables.push(format!("${name}"));
+                }
+            }
+        }
+        rest = &after_dollar[name_length..];
+    }
+    expanded.push_str(rest);
+    (expanded, unset_variables)
+}
@@ -0,0 +1,176 @@
+use std::{collections::VecDeque, fmt};
+
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeDelta, Weekday};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::config::UrgencyLevel;
+
+#[cfg(not(test))]
+use crate::notifications::send_profile_notification;
+
+#[cfg(test)]
+use crate::tests::send_profile_notification;
+
+/// The charger has to be connected for more than half of this window to count as docked
+const DOCKED_WINDOW_SECONDS: i64 = 60 * 60;
+
+// ----------------------------------------------------------------
+// Clock Trait, so rules can be tested with a fake clock
+// ----------------------------------------------------------------
+pub trait Clock {
+    /// The current local date and time
+    fn now(&self) -> NaiveDateTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+// ----------------------------------------------------------------
+// Profile Rule Struct and Implementation
+// ----------------------------------------------------------------
+/// Activates `profile` while every condition that is set matches
+/// e.g. `{ profile = "desk", days = ["mon", "fri"], start = "09:00", end = "17:00", docked = true }`
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct ProfileRule {
+    pub profile: String,
+    days: Option<Vec<String>>,
+    start: Option<String>,
+    end: Option<String>,
+    docked: Option<bool>,
+}
+
+impl ProfileRule {
+    pub fn matches(&self, now: NaiveDateTime, docked: bool) -> bool {
+        if let Some(days) = &self.days
+            && !days.iter().filter_map(|day| day.parse::<Weekday>().ok()).any(|day| day == now.weekday()) {
+            return false;
+        }
+
+        let start = self.start.as_deref().and_then(parse_time);
+        let end = self.end.as_deref().and_then(parse_time);
+        let time = now.time();
+        let in_time_window = match (start, end) {
+            (Some(start), Some(end)) if start <= end => start <= time && time < end,
+            // Overnight windows, e.g. 22:00 to 06:00
+            (Some(start), Some(end)) => start <= time || time < end,
+            (Some(start), None) => start <= time,
+            (None, Some(end)) => time < end,
+            (None, None) => true,
+        };
+        if !in_time_window {
+            return false;
+        }
+
+        self.docked.is_none_or(|should_be_docked| should_be_docked == docked)
+    }
+
+    /// Returns a description of every invalid condition in this rule
+    pub fn invalid_conditions(&self) -> Vec<String> {
+        let mut invalid_conditions = Vec::new();
+        for day in self.days.iter().flatten() {
+            if day.parse::<Weekday>().is_err() {
+                invalid_conditions.push(format!("invalid day \"{day}\", expected e.g. \"mon\" or \"monday\""));
+            }
+        }
+        for time in [&self.start, &self.end].into_iter().flatten() {
+            if parse_time(time).is_none() {
+                invalid_conditions.push(format!("invalid time \"{time}\", expected \"HH:MM\""));
+            }
+        }
+        invalid_conditions
+    }
+}
+
+/// The conditions of the rule as written, e.g. "days = mon, fri, start = 09:00, docked = true"
+impl fmt::Display for ProfileRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut conditions = Vec::new();
+        if let Some(days) = &self.days {
+            conditions.push(format!("days = {}", days.join(", ")));
+        }
+        if let Some(start) = &self.start {
+            conditions.push(format!("start = {start}"));
+        }
+        if let Some(end) = &self.end {
+            conditions.push(format!("end = {end}"));
+        }
+        if let Some(docked) = self.docked {
+            conditions.push(format!("docked = {docked}"));
+        }
+        if conditions.is_empty() {
+            return write!(f, "no conditions");
+        }
+        write!(f, "{}", conditions.join(", "))
+    }
+}
+
+fn parse_time(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time.trim(), "%H:%M").ok()
+}
+
+// ----------------------------------------------------------------
+// Profile Selector Struct and Implementation
+// ----------------------------------------------------------------
+/// Picks the profile from the first matching rule,
+/// and keeps the charger history needed for the docked condition
+pub struct ProfileSelector<C: Clock> {
+    clock: C,
+    /// (time, charging) samples from the last hour, oldest first
+    charger_history: VecDeque<(NaiveDateTime, bool)>,
+}
+
+impl<C: Clock> ProfileSelector<C> {
+
+    pub fn new(clock: C) -> Self {
+        ProfileSelector { clock, charger_history: VecDeque::new() }
+    }
+
+    /// Records whether the charger is connected right now, should be called on every battery update
+    pub fn record_charger_state(&mut self, charging: bool) {
+        let now = self.clock.now();
+        self.charger_history.push_back((now, charging));
+
+        // Keep one sample older than the window so the start of the window is still covered
+        let window_start = now - TimeDelta::seconds(DOCKED_WINDOW_SECONDS);
+        while self.charger_history.get(1).is_some_and(|(time, _)| *time <= window_start) {
+            self.charger_history.pop_front();
+        }
+    }
+
+    /// Docked means the charger was connected for most of the last hour
+    pub fn is_docked(&self) -> bool {
+        let now = self.clock.now();
+        let window_start = now - TimeDelta::seconds(DOCKED_WINDOW_SECONDS);
+
+        // Each sample's state lasts until the next sample
+        let next_times = self.charger_history.iter().skip(1).map(|(time, _)| *time).chain([now]);
+        let charging_seconds: i64 = self.charger_history
+            .iter()
+            .zip(next_times)
+            .filter(|((_, charging), _)| *charging)
+            .map(|((time, _), next_time)| (next_time - (*time).max(window_start)).num_seconds().max(0))
+            .sum();
+
+        charging_seconds * 2 > DOCKED_WINDOW_SECONDS
+    }
+
+    /// The first rule that matches right now and its index in `profile_rules`, None if no rule matches
+    pub fn matching_rule<'a>(&self, rules: &'a [ProfileRule]) -> Option<(usize, &'a ProfileRule)> {
+        let now = self.clock.now();
+        let docked = self.is_docked();
+        rules.iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(now, docked))
+    }
+}
+
+/// Sends a notification about the new profile
+pub fn announce_profile_switch(profile: &str, notif_time: i32) {
+    send_profile_notification(profile, UrgencyLevel::Low.into(), notif_time);
+}
@@ -0,0 +1,142 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::warn;
+
+use crate::cli_args::ServiceCommand;
+use crate::config_file_manager::xdg_dir_from_env;
+
+const UNIT_NAME: &str = "spider-volt.service";
+
+/// `$XDG_CONFIG_HOME/systemd/user`, where systemd looks for units installed by the user
+pub fn user_unit_dir() -> Option<PathBuf> {
+    let config_home = xdg_dir_from_env("XDG_CONFIG_HOME")
+        .or_else(|| env::home_dir().map(|home_directory| home_directory.join(".config")))?;
+    Some(config_home.join("systemd").join("user"))
+}
+
+/// The user unit for the binary at `binary_path`, the shipped systemd/spider-volt.service is this for /usr/bin/spider-volt
+/// Started with the graphical session so the notification server is running, and stopped with it
+pub fn unit_file(binary_path: &Path) -> String {
+    format!("\
+[Unit]
+Description=A lightweight battery notification daemon written in Rust
+Documentation=https://github.com/ahmadlgohary/spider-volt
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+# READY=1 is sent once the battery is read, STATUS= shows the battery in `systemctl --user status`
+Type=notify
+NotifyAccess=main
+ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
+# The daemon pings the watchdog from its loop, a loop stuck on a battery read is restarted
+WatchdogSec=30
+Restart=always
+
+[Install]
+WantedBy=graphical-session.target
+", exec_start(binary_path))
+}
+
+/// Quotes the path if needed, `%` starts a specifier in unit files and is escaped as `%%`
+fn exec_start(binary_path: &Path) -> String {
+    let path = binary_path.to_string_lossy()
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    if path.contains(char::is_whitespace) {
+        format!("\"{path}\"")
+    } else {
+        path
+    }
+}
+
+// ----------------------------------------------------------------
+// Systemctl Trait, so installing can be tested without a user manager
+// ----------------------------------------------------------------
+pub trait Systemctl {
+    /// Runs `systemctl --user` with the arguments
+    fn run(&mut self, arguments: &[&str]) -> Result<(), String>;
+}
+
+pub struct UserSystemctl;
+
+impl Systemctl for UserSystemctl {
+    fn run(&mut self, arguments: &[&str]) -> Result<(), String> {
+        let command = format!("systemctl --user {}", arguments.join(" "));
+        match Command::new("systemctl").arg("--user").args(arguments).status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(format!("`{command}` failed with {status}")),
+            Err(error) => Err(format!("Could not run `{command}`\n\t {error}")),
+        }
+    }
+}
+
+// ----------------------------------------------------------------
+// Service Commands
+// ----------------------------------------------------------------
+/// Writes the unit for this binary to the user unit dir, and enables and starts it if `enable` is set
+pub fn install_service(unit_dir: &Path, binary_path: &Path, enable: bool, systemctl: &mut impl Systemctl) -> Result<(), String> {
+    let unit_path = unit_dir.join(UNIT_NAME);
+    let unit = unit_file(binary_path);
+    if fs::read_to_string(&unit_path).is_ok_and(|installed_unit| installed_unit == unit) {
+        println!("{} is up to date", unit_path.display());
+    } else {
+        fs::create_dir_all(unit_dir)
+            .and_then(|()| fs::write(&unit_path, unit))
+            .map_err(|error| format!("Could not write {}\n\t {error}", unit_path.display()))?;
+        println!("Wrote {} for {}", unit_path.display(), binary_path.display());
+    }
+
+    systemctl.run(&["daemon-reload"])?;
+    if enable {
+        systemctl.run(&["enable", "--now", UNIT_NAME])?;
+        println!("Enabled and started {UNIT_NAME}");
+    } else {
+        println!("Run `systemctl --user enable --now {UNIT_NAME}` to start it with the graphical session");
+    }
+    Ok(())
+}
+
+/// Stops and disables the unit and removes it from the user unit dir
+pub fn uninstall_service(unit_dir: &Path, systemctl: &mut impl Systemctl) -> Result<(), String> {
+    let unit_path = unit_dir.join(UNIT_NAME);
+    if !unit_path.exists() {
+        return Err(format!("{} does not exist, the service is not installed", unit_path.display()));
+    }
+
+    // A unit that was never enabled or started is still removed
+    if let Err(error) = systemctl.run(&["disable", "--now", UNIT_NAME]) {
+        warn!("{error}");
+    }
+    fs::remove_file(&unit_path).map_err(|error| format!("Could not remove {}\n\t {error}", unit_path.display()))?;
+    println!("Removed {}", unit_path.display());
+    systemctl.run(&["daemon-reload"])
+}
+
+/// Runs a `spider-volt service` command, the unit runs the binary this command was run from
+pub fn run_service_command(command: &ServiceCommand) -> Result<(), String> {
+    match command {
+        ServiceCommand::Print => {
+            print!("{}", unit_file(&binary_path()?));
+            Ok(())
+        },
+        ServiceCommand::Install { enable } => {
+            let unit_dir = user_unit_dir().ok_or("Could not get the config dir for the user unit")?;
+            install_service(&unit_dir, &binary_path()?, *enable, &mut UserSystemctl)
+        },
+        ServiceCommand::Uninstall => {
+            let unit_dir = user_unit_dir().ok_or("Could not get the config dir for the user unit")?;
+            uninstall_service(&unit_dir, &mut UserSystemctl)
+        },
+    }
+}
+
+fn binary_path() -> Result<PathBuf, String> {
+    env::current_exe().map_err(|error| format!("Could not get the path of the spider-volt binary\n\t {error}"))
+}
@@ -0,0 +1,67 @@
+use std::sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc};
+
+use log::warn;
+use signal_hook::{consts::{SIGINT, SIGTERM, SIGUSR1}, flag, low_level::signal_name};
+
+#[cfg(not(test))]
+use crate::{audio::drain_audio_worker, notifications::close_last_notification};
+
+#[cfg(test)]
+use crate::tests::{close_last_notification, drain_audio_worker};
+
+//  ----------------------------------------------------
+//  Signals Struct and Implementation
+//  ----------------------------------------------------
+/// SIGTERM and SIGINT stop the daemon loop, SIGUSR1 asks for the status to be logged
+/// The handlers only set flags, the daemon loop checks them once per iteration
+pub struct Signals {
+    /// The number of the signal that asked the daemon to stop, 0 if none did
+    shutdown_signal: Arc<AtomicUsize>,
+    status_requested: Arc<AtomicBool>,
+}
+
+impl Signals {
+
+    pub fn register() -> Self {
+        let signals = Signals {
+            shutdown_signal: Arc::new(AtomicUsize::new(0)),
+            status_requested: Arc::new(AtomicBool::new(false)),
+        };
+
+        let shutting_down = Arc::new(AtomicBool::new(false));
+        for signal in [SIGTERM, SIGINT] {
+            // A second SIGTERM or SIGINT exits right away, in case the shutdown hangs
+            let registered = flag::register_conditional_shutdown(signal, 1, Arc::clone(&shutting_down))
+                .and_then(|_| flag::register(signal, Arc::clone(&shutting_down)))
+                .and_then(|_| flag::register_usize(signal, Arc::clone(&signals.shutdown_signal), signal as usize));
+            if let Err(error) = registered {
+                warn!("Could not register a {} handler, it stops the daemon without a clean shutdown\n\t {error:?}",
+                    signal_name(signal).unwrap_or("signal"));
+            }
+        }
+
+        if let Err(error) = flag::register(SIGUSR1, Arc::clone(&signals.status_requested)) {
+            warn!("Could not register SIGUSR1 handler, logging the status on SIGUSR1 is disabled\n\t {error:?}");
+        }
+        signals
+    }
+
+    /// The name of the signal that asked the daemon to stop, e.g. "SIGTERM"
+    pub fn shutdown_requested(&self) -> Option<&'static str> {
+        match self.shutdown_signal.load(Ordering::Relaxed) {
+            0 => None,
+            signal => Some(signal_name(signal as i32).unwrap_or("a signal")),
+        }
+    }
+
+    /// True once for every SIGUSR1 received since the last call
+    pub fn status_requested(&self) -> bool {
+        self.status_requested.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Lets the queued sounds finish and closes the last notification, so neither outlives the daemon
+pub fn finish_notifications() {
+    drain_audio_worker();
+    close_last_notification();
+}
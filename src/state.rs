@@ -0,0 +1,80 @@
+use std::{collections::BTreeSet, env, fs, io, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::DEFAULT_PROFILE;
+use crate::config_file_manager::xdg_dir_from_env;
+
+const APP_NAME: &str = "spider-volt";
+const ACTIVE_PROFILE_FILE_NAME: &str = "active_profile";
+const NOTIFICATION_STATE_FILE_NAME: &str = "notification_state.json";
+
+/// Directory for state that should survive restarts, `$XDG_STATE_HOME/spider-volt`
+/// falls back to `~/.local/state/spider-volt` if `$XDG_STATE_HOME` is unset
+pub fn state_dir() -> Option<PathBuf> {
+    let state_home = xdg_dir_from_env("XDG_STATE_HOME")
+        .or_else(|| env::home_dir().map(|home_directory| home_directory.join(".local").join("state")))?;
+    Some(state_home.join(APP_NAME))
+}
+
+pub fn active_profile_path() -> Option<PathBuf> {
+    state_dir().map(|state_dir| state_dir.join(ACTIVE_PROFILE_FILE_NAME))
+}
+
+/// Reads the profile persisted by `save_active_profile`, None if no profile is active
+pub fn load_active_profile() -> Option<String> {
+    let profile = fs::read_to_string(active_profile_path()?).ok()?;
+    let profile = profile.trim();
+    if profile.is_empty() || profile == DEFAULT_PROFILE {
+        return None;
+    }
+    Some(profile.to_string())
+}
+
+/// Persists the active profile so it is used again after a restart,
+/// a running daemon watches this file and switches to the new profile
+pub fn save_active_profile(profile: &str) -> io::Result<()> {
+    let path = active_profile_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Could not get the state dir"))?;
+    if let Some(state_dir) = path.parent() {
+        fs::create_dir_all(state_dir)?;
+    }
+    fs::write(path, format!("{}\n", profile.trim()))
+}
+
+// ----------------------------------------------------------------
+// Notification State, so a restart does not send the same notifications again
+// ----------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationState {
+    /// The last charger state that was notified, e.g. "Discharging"
+    pub state: String,
+    /// Thresholds that already fired since the charger state last changed
+    pub sent_thresholds: BTreeSet<u8>,
+}
+
+pub fn notification_state_path() -> Option<PathBuf> {
+    state_dir().map(|state_dir| state_dir.join(NOTIFICATION_STATE_FILE_NAME))
+}
+
+/// Reads the state written by `save_notification_state`, None if it was never saved
+pub fn load_notification_state(path: &Path) -> Result<Option<NotificationState>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(format!("Could not read {}\n\t {error}", path.display())),
+    };
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|error| format!("Could not parse {}\n\t {error}", path.display()))
+}
+
+/// Written to a temporary file first, so a crash while saving never leaves half a file behind
+pub fn save_notification_state(path: &Path, notification_state: &NotificationState) -> io::Result<()> {
+    if let Some(state_dir) = path.parent() {
+        fs::create_dir_all(state_dir)?;
+    }
+    let temporary_path = path.with_extension("json.tmp");
+    fs::write(&temporary_path, format!("{}\n", serde_json::to_string(notification_state)?))?;
+    fs::rename(temporary_path, path)
+}
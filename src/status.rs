@@ -0,0 +1,144 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Local};
+use log::error;
+use serde::Serialize;
+
+use crate::battery_monitor::{self, BatteryStats};
+use crate::config::DEFAULT_PROFILE;
+use crate::control_socket::{self, send_request, ControlError, DaemonStatus, Request};
+use crate::state;
+
+/// Where the status came from, fired thresholds and the snooze are only known to the daemon
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusSource {
+    Daemon,
+    Standalone,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub source: StatusSource,
+    #[serde(flatten)]
+    pub status: DaemonStatus,
+}
+
+/// The status the daemon sends in reply to `status`
+pub fn battery_status(
+        battery_stats: &BatteryStats,
+        profile: &str,
+        battery_notif_sent: &HashSet<u8>,
+        snoozed_until: Option<DateTime<Local>>
+    ) -> DaemonStatus {
+    let mut fired_thresholds: Vec<u8> = battery_notif_sent.iter().copied().collect();
+    fired_thresholds.sort_unstable();
+    DaemonStatus {
+        percentage: battery_stats.percentage(),
+        state: battery_stats.state().to_string(),
+        time_remaining: battery_stats.time_remaining(),
+        profile: profile.to_string(),
+        fired_thresholds,
+        snoozed_until: snoozed_until.map(|until| until.to_rfc3339()),
+    }
+}
+
+/// Prints the status for `spider-volt status`, returns false if it could not be read
+pub fn print_status(json: bool, standalone: bool) -> bool {
+    let report = if standalone { standalone_status() } else { daemon_status() };
+    let report = match report {
+        Ok(report) => report,
+        Err(error) => {
+            error!("{error}");
+            return false;
+        }
+    };
+
+    if json {
+        match serde_json::to_string(&report) {
+            Ok(report) => println!("{report}"),
+            Err(error) => {
+                error!("Could not serialize the status: {error}");
+                return false;
+            }
+        }
+    } else {
+        print!("{}", format_status(&report));
+    }
+    true
+}
+
+fn daemon_status() -> Result<StatusReport, String> {
+    let socket_path = control_socket::socket_path().ok_or(ControlError::NoRuntimeDir.to_string())?;
+    match send_request(&socket_path, &Request::Status) {
+        Ok(response) => response.status
+            .map(|status| StatusReport { source: StatusSource::Daemon, status })
+            .ok_or_else(|| ControlError::InvalidResponse("the response has no status".to_string()).to_string()),
+        Err(error @ ControlError::NotRunning(_)) => Err(format!("{error}\nUse --standalone to read the battery without the daemon")),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Reads the battery like the daemon does, the profile is the one saved with --profile or --set-profile
+fn standalone_status() -> Result<StatusReport, String> {
+    // The battery helpers print what went wrong
+    let battery_error = || "Could not read the battery".to_string();
+    let manager = battery_monitor::init_battery_manager().ok_or_else(battery_error)?;
+    let mut battery = battery_monitor::init_battery(&manager).ok_or_else(battery_error)?;
+    let battery_stats = BatteryStats::new(&manager, &mut battery).ok_or_else(battery_error)?;
+
+    let profile = state::load_active_profile().unwrap_or(DEFAULT_PROFILE.to_string());
+    Ok(StatusReport {
+        source: StatusSource::Standalone,
+        status: battery_status(&battery_stats, &profile, &HashSet::new(), None),
+    })
+}
+
+/// One "Label: value" line per field, for people rather than scripts
+pub fn format_status(report: &StatusReport) -> String {
+    let status = &report.status;
+    let time_remaining = format_time_remaining(status);
+    let (fired_thresholds, snoozed) = match report.source {
+        StatusSource::Standalone => ("unknown with --standalone".to_string(), "unknown with --standalone".to_string()),
+        StatusSource::Daemon => (
+            if status.fired_thresholds.is_empty() {
+                "none".to_string()
+            } else {
+                status.fired_thresholds.iter().map(|threshold| format!("{threshold}%")).collect::<Vec<_>>().join(", ")
+            },
+            match &status.snoozed_until {
+                Some(until) => match DateTime::parse_from_rfc3339(until) {
+                    Ok(until) => format!("until {}", until.format("%H:%M")),
+                    Err(_) => format!("until {until}"),
+                },
+                None => "no".to_string(),
+            },
+        ),
+    };
+
+    format!(
+        "Battery:   {}%, {}\n\
+         Remaining: {time_remaining}\n\
+         Profile:   {}\n\
+         Fired:     {fired_thresholds}\n\
+         Snoozed:   {snoozed}\n",
+        status.percentage, status.state, status.profile)
+}
+
+/// e.g. "1h 23m until empty" or "12m until full"
+pub fn format_time_remaining(status: &DaemonStatus) -> String {
+    match (status.time_remaining, status.state.as_str()) {
+        (Some(seconds), "Charging") => format!("{} until full", format_duration(seconds)),
+        (Some(seconds), _) => format!("{} until empty", format_duration(seconds)),
+        (None, _) => "unknown".to_string(),
+    }
+}
+
+/// e.g. "1h 05m", or "12m" under an hour
+fn format_duration(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    match minutes / 60 {
+        0 => format!("{minutes}m"),
+        hours => format!("{hours}h {:02}m", minutes % 60),
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    env, io,
+    os::{linux::net::SocketAddrExt, unix::net::{SocketAddr, UnixDatagram}},
+    process,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::control_socket::DaemonStatus;
+
+//  ----------------------------------------------------
+//  SystemdNotifier Struct and Implementation
+//  ----------------------------------------------------
+/// Tells systemd when the daemon is ready, what it is doing and that it is still alive,
+/// the sd_notify protocol without linking libsystemd: one datagram of "KEY=value" lines per message
+/// Only used when started by a `Type=notify` unit, which sets `$NOTIFY_SOCKET`
+pub struct SystemdNotifier {
+    socket: UnixDatagram,
+    address: SocketAddr,
+    /// Half of `WatchdogSec=`, as recommended by sd_watchdog_enabled(3), None without a watchdog
+    watchdog_interval: Option<Duration>,
+    last_watchdog_ping: Option<Instant>,
+    last_status: Option<String>,
+}
+
+impl SystemdNotifier {
+
+    /// None if the daemon was not started by a `Type=notify` unit
+    pub fn from_env() -> Option<Self> {
+        let notify_socket = env::var("NOTIFY_SOCKET").ok()?;
+        let watchdog_interval = watchdog_interval(
+            env::var("WATCHDOG_USEC").ok().as_deref(),
+            env::var("WATCHDOG_PID").ok().as_deref(),
+            process::id());
+        match SystemdNotifier::connect(&notify_socket, watchdog_interval) {
+            Ok(notifier) => Some(notifier),
+            Err(error) => {
+                warn!("Could not use the systemd notify socket {notify_socket}, readiness is not reported\n\t {error:?}");
+                None
+            }
+        }
+    }
+
+    /// `notify_socket` is a path, or an abstract socket name if it starts with "@"
+    pub fn connect(notify_socket: &str, watchdog_interval: Option<Duration>) -> io::Result<Self> {
+        let address = match notify_socket.strip_prefix('@') {
+            Some(abstract_name) => SocketAddr::from_abstract_name(abstract_name)?,
+            None => SocketAddr::from_pathname(notify_socket)?,
+        };
+        Ok(SystemdNotifier {
+            socket: UnixDatagram::unbound()?,
+            address,
+            watchdog_interval,
+            last_watchdog_ping: None,
+            last_status: None,
+        })
+    }
+
+    /// Sent once the battery is read and the loop is about to start, systemd then considers the unit started
+    pub fn ready(&mut self, status: &DaemonStatus) {
+        let status_line = status_line(status);
+        self.notify(&format!("READY=1\nSTATUS={status_line}"));
+        self.last_status = Some(status_line);
+    }
+
+    /// Shown by `systemctl --user status`, only sent when it changed
+    pub fn status(&mut self, status: &DaemonStatus) {
+        let status_line = status_line(status);
+        if self.last_status.as_ref() != Some(&status_line) {
+            self.notify(&format!("STATUS={status_line}"));
+            self.last_status = Some(status_line);
+        }
+    }
+
+    /// Called every loop iteration, pings at most once per watchdog interval
+    /// A loop stuck on a battery read stops pinging and systemd restarts the daemon
+    pub fn watchdog(&mut self) {
+        let Some(watchdog_interval) = self.watchdog_interval else { return };
+        if self.last_watchdog_ping.is_some_and(|last_ping| last_ping.elapsed() < watchdog_interval) {
+            return;
+        }
+        self.notify("WATCHDOG=1");
+        self.last_watchdog_ping = Some(Instant::now());
+    }
+
+    pub fn stopping(&mut self) {
+        self.notify("STOPPING=1");
+    }
+
+    fn notify(&self, message: &str) {
+        if let Err(error) = self.socket.send_to_addr(message.as_bytes(), &self.address) {
+            warn!("Could not notify systemd\n\t {error:?}");
+        }
+    }
+}
+
+/// Half of `$WATCHDOG_USEC`, None if the watchdog is off or meant for another process
+pub fn watchdog_interval(watchdog_usec: Option<&str>, watchdog_pid: Option<&str>, pid: u32) -> Option<Duration> {
+    if let Some(watchdog_pid) = watchdog_pid
+        && watchdog_pid.parse::<u32>().ok() != Some(pid) {
+        return None;
+    }
+    match watchdog_usec?.parse::<u64>().ok()? {
+        0 => None,
+        watchdog_usec => Some(Duration::from_micros(watchdog_usec / 2)),
+    }
+}
+
+/// e.g. "42%, Discharging, profile default"
+fn status_line(status: &DaemonStatus) -> String {
+    let mut status_line = format!("{}%, {}, profile {}", status.percentage, status.state, status.profile);
+    if status.snoozed_until.is_some() {
+        status_line.push_str(", snoozed");
+    }
+    status_line
+}
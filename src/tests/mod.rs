@@ -8,14 +8,32 @@
  * */
 
 #![cfg(test)]
-use std::sync::Mutex;
+use std::{env, fs, path::{Path, PathBuf}, sync::Mutex};
 
 use notify_rust::Urgency;
 use rstest::{fixture, rstest};
 
 use crate::{battery_monitor::BatteryStats, config::Config};
 
+mod test_bar;
 mod test_battery_monitors;
+mod test_config;
+mod test_config_editor;
+mod test_config_includes;
+mod test_config_overrides;
+mod test_config_template;
+mod test_control_socket;
+mod test_events;
+mod test_init_wizard;
+mod test_instance_lock;
+mod test_logging;
+mod test_notification_state;
+mod test_profiles;
+mod test_profile_rules;
+mod test_service;
+mod test_shutdown;
+mod test_status;
+mod test_systemd;
 
 
 /* *
@@ -36,15 +54,52 @@ fn get_calls() -> Vec<String> {
     TEST_CALLS.lock().unwrap().clone()
 }
 
+/// A directory of its own for a test, removed when dropped
+/// `name` has to be unique across the tests, the pid keeps two test runs apart
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = env::temp_dir().join(format!("spider-volt-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn join(&self, relative_path: &str) -> PathBuf {
+        self.path.join(relative_path)
+    }
+
+    /// Creates the directories in between, returns the path of the file
+    fn write(&self, relative_path: &str, contents: &str) -> PathBuf {
+        let path = self.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
 impl Config {
     pub fn parse_toml_from_str(config_str: &str) -> Result<Self, toml::de::Error>{
-        toml::from_str(&config_str)
+        toml::from_str(config_str)
     }
 }
 
 impl BatteryStats {
     fn parse_toml_from_str(battery_str: &str) -> Result<Self, toml::de::Error>{
-        toml::from_str(&battery_str)
+        toml::from_str(battery_str)
     }
 }
 
@@ -78,6 +133,34 @@ pub fn send_charger_notification(
             .lock()
             .unwrap()
             // make sure we send at the correct battery state 
-            .push(format!("{charging_state}"));
-} 
+            .push(charging_state.to_string());
+}
+
 
+pub fn send_profile_notification(
+        profile: &str,
+        _notif_urgency: Urgency,
+        _time: i32
+    ) {
+       TEST_CALLS
+            .lock()
+            .unwrap()
+            // make sure we announce the correct profile
+            .push(format!("profile {profile}"));
+}
+
+
+pub fn drain_audio_worker() {
+       TEST_CALLS
+            .lock()
+            .unwrap()
+            .push("drain audio".to_string());
+}
+
+
+pub fn close_last_notification() {
+       TEST_CALLS
+            .lock()
+            .unwrap()
+            .push("close notification".to_string());
+}
@@ -0,0 +1,112 @@
+use super::*;
+use crate::bar::{bar_level, render_bar, BarLevel, BarMode};
+use crate::control_socket::DaemonStatus;
+
+const CONFIG_STR: &str = r#"
+[low_battery_levels.20]
+message = "Battery Low"
+
+[low_battery_levels.10]
+message = "Battery Critically Low"
+urgent_level = "Critical"
+"#;
+
+fn status(state: &str, percentage: i32) -> DaemonStatus {
+    DaemonStatus {
+        percentage,
+        state: state.to_string(),
+        time_remaining: Some(4980),
+        profile: "default".to_string(),
+        fired_thresholds: Vec::new(),
+        snoozed_until: None,
+    }
+}
+
+fn config(config_str: &str) -> Config {
+    let mut config = Config::parse_toml_from_str(config_str).unwrap();
+    config.resolve_inheritance();
+    config.apply_sound_and_urgency_filters();
+    config
+}
+
+#[rstest]
+#[case("Charging", 5, BarLevel::Charging)]
+#[case("Discharging", 21, BarLevel::Discharging)]
+#[case("Discharging", 20, BarLevel::Warning)]
+#[case("Discharging", 11, BarLevel::Warning)]
+#[case("Discharging", 10, BarLevel::Critical)]
+#[case("Unknown", 3, BarLevel::Critical)]
+fn levels_change_at_the_notification_thresholds(#[case] state: &str, #[case] percentage: i32, #[case] expected: BarLevel) {
+    assert_eq!(bar_level(&status(state, percentage), &config(CONFIG_STR).low_battery_levels), expected);
+}
+
+#[rstest]
+fn filtered_notifications_do_not_change_the_level() {
+    let configuration = config(&format!("minimum_urgency = \"critical\"\n{CONFIG_STR}"));
+    assert_eq!(bar_level(&status("Discharging", 15), &configuration.low_battery_levels), BarLevel::Discharging);
+    assert_eq!(bar_level(&status("Discharging", 10), &configuration.low_battery_levels), BarLevel::Critical);
+}
+
+#[rstest]
+#[case(BarMode::Waybar, 42,
+    r#"{"class":"discharging","percentage":42,"text":"42%","tooltip":"Discharging, 1h 23m until empty\nProfile: default"}"#)]
+#[case(BarMode::Waybar, 8,
+    r#"{"class":"critical","percentage":8,"text":"8%","tooltip":"Discharging, 1h 23m until empty\nProfile: default"}"#)]
+#[case(BarMode::I3blocks, 42, r#"{"full_text":"42%","short_text":"42%"}"#)]
+#[case(BarMode::I3blocks, 15, r##"{"color":"#ff5555","full_text":"15%","short_text":"15%"}"##)]
+#[case(BarMode::Polybar, 42, "42%")]
+#[case(BarMode::Polybar, 8, "%{F#ff5555}8%%{F-}")]
+fn default_output_per_mode(#[case] mode: BarMode, #[case] percentage: i32, #[case] expected: &str) {
+    assert_eq!(render_bar(mode, &config(CONFIG_STR), &status("Discharging", percentage)), expected);
+}
+
+#[rstest]
+#[case(BarMode::Waybar, 21, r#"{"class":"discharging","percentage":21,"text":"21%","tooltip":"Discharging, 1h 23m until empty\nProfile: default"}"#)]
+#[case(BarMode::Waybar, 20, r#"{"class":"warning","percentage":20,"text":"20%","tooltip":"Discharging, 1h 23m until empty\nProfile: default"}"#)]
+#[case(BarMode::I3blocks, 20, r##"{"color":"#ff5555","full_text":"20%","short_text":"20%"}"##)]
+#[case(BarMode::Polybar, 20, "%{F#ff5555}20%%{F-}")]
+fn the_default_config_turns_red_where_its_notification_fires(#[case] mode: BarMode, #[case] percentage: i32, #[case] expected: &str) {
+    let mut configuration = Config::default();
+    configuration.resolve_inheritance();
+    configuration.apply_sound_and_urgency_filters();
+    assert_eq!(render_bar(mode, &configuration, &status("Discharging", percentage)), expected);
+}
+
+#[rstest]
+fn formats_and_styles_are_configurable() {
+    let configuration = config(&format!(r##"{CONFIG_STR}
+        [bar]
+        format = "{{percentage}}% ({{time_remaining}})"
+        tooltip_format = "{{profile}}"
+
+        [bar.warning]
+        class = "low"
+        color = "#ff0000"
+    "##));
+
+    assert_eq!(render_bar(BarMode::Waybar, &configuration, &status("Discharging", 15)),
+        r#"{"class":"low","percentage":15,"text":"15% (1h 23m until empty)","tooltip":"default"}"#);
+    assert_eq!(render_bar(BarMode::Polybar, &configuration, &status("Discharging", 15)),
+        "%{F#ff0000}15% (1h 23m until empty)%{F-}");
+}
+
+#[rstest]
+fn bar_config_is_layered() {
+    let mut configuration = config(&format!(
+        "{CONFIG_STR}\n[bar]\nformat = \"{{percentage}}\"\n[bar.critical]\nclass = \"red\"\ncolor = \"#ff0000\"\n"));
+    configuration.layer(config("[bar.critical]\ncolor = \"#00ff00\"\n"));
+
+    assert_eq!(render_bar(BarMode::Polybar, &configuration, &status("Discharging", 5)), "%{F#00ff00}5%{F-}");
+    assert_eq!(render_bar(BarMode::Waybar, &configuration, &status("Discharging", 5)),
+        r#"{"class":"red","percentage":5,"text":"5","tooltip":"Discharging, 1h 23m until empty\nProfile: default"}"#);
+}
+
+#[rstest]
+#[case("[bar]\nformat = \"{battery}%\"\n", "bar.format", "unknown placeholder {battery}")]
+#[case("[bar]\ntooltip_format = \"{state} {eta}\"\n", "bar.tooltip_format", "unknown placeholder {eta}")]
+#[case("[bar.critical]\ncolor = \"red\"\n", "bar.critical.color", "\"red\" is not a color")]
+#[case("[bar.warning]\ncolor = \"#ffaa0\"\n", "bar.warning.color", "is not a color")]
+fn invalid_bar_config_is_reported(#[case] config_str: &str, #[case] key: &str, #[case] message: &str) {
+    let issues = Config::parse_toml_from_str(config_str).unwrap().validate();
+    assert!(issues.iter().any(|issue| issue.key == key && issue.message.contains(message)), "{issues:?}");
+}
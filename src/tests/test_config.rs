@@ -0,0 +1,439 @@
+use std::{collections::HashSet, env, fs, path::{Path, PathBuf}};
+
+use super::*;
+use crate::config::{GetUrgency, Severity, UrgencyLevel};
+use crate::config_file_manager::{config_schema, load_config_chain, locate_key, system_config_paths_in, ConfigFormat};
+use crate::config_reloader::ConfigReloader;
+use crate::path_expansion::{expand_file_path, expand_icon, expand_variables, ExpandPaths};
+
+fn home() -> String {
+    env::home_dir().unwrap().display().to_string()
+}
+
+#[rstest]
+#[case("~/sounds/low.ogg", format!("{}/sounds/low.ogg", home()))]
+#[case("$HOME/sounds/low.ogg", format!("{}/sounds/low.ogg", home()))]
+#[case("${HOME}/sounds/low.ogg", format!("{}/sounds/low.ogg", home()))]
+#[case("${SPIDER_VOLT_UNSET_VAR:-~/share}/low.ogg", format!("{}/share/low.ogg", home()))]
+#[case("/usr/share/sounds/low.ogg", "/usr/share/sounds/low.ogg".to_string())]
+#[case("sounds/low.ogg", "/etc/spider-volt/sounds/low.ogg".to_string())]
+#[case("", "".to_string())]
+fn file_paths_are_expanded(#[case] raw_path: &str, #[case] expected: String) {
+    assert_eq!(expand_file_path(raw_path, Path::new("/etc/spider-volt")), expected);
+}
+
+#[rstest]
+#[case("$SPIDER_VOLT_UNSET_VAR/low.ogg")]
+#[case("sounds/$SPIDER_VOLT_UNSET_VAR/low.ogg")]
+#[case("sounds/${SPIDER_VOLT_UNSET_VAR}.ogg")]
+fn unset_variables_are_left_untouched(#[case] raw_path: &str) {
+    // Joining the config dir in front would only hide the variable
+    assert_eq!(expand_file_path(raw_path, Path::new("/etc/spider-volt")), raw_path);
+}
+
+/// Variables seen by the expansion tests, which cannot change the real environment
+fn fake_variable(name: &str) -> Option<String> {
+    match name {
+        "SOUNDS" => Some("/opt/sounds".to_string()),
+        "EMPTY" => Some(String::new()),
+        "XDG_CONFIG_HOME" => Some("relative/config".to_string()),
+        _ => None,
+    }
+}
+
+#[rstest]
+#[case("$SOUNDS/low.ogg", "/opt/sounds/low.ogg".to_string())]
+#[case("${SOUNDS}/low.ogg", "/opt/sounds/low.ogg".to_string())]
+#[case("$EMPTY/low.ogg", "$EMPTY/low.ogg".to_string())]
+#[case("${EMPTY}/low.ogg", "${EMPTY}/low.ogg".to_string())]
+#[case("${EMPTY:-/usr/share}/low.ogg", "/usr/share/low.ogg".to_string())]
+#[case("${XDG_DATA_HOME}/sounds/low.ogg", format!("{}/.local/share/sounds/low.ogg", home()))]
+#[case("$XDG_STATE_HOME/low.ogg", format!("{}/.local/state/low.ogg", home()))]
+#[case("${XDG_DATA_HOME:-/usr/share}/low.ogg", "/usr/share/low.ogg".to_string())]
+#[case("${XDG_CONFIG_HOME}/low.ogg", format!("{}/.config/low.ogg", home()))]
+fn empty_and_xdg_variables_are_expanded(#[case] raw_path: &str, #[case] expected: String) {
+    assert_eq!(expand_variables(raw_path, fake_variable).0, expected);
+}
+
+#[rstest]
+#[case("$SOUNDS/low.ogg", vec![])]
+#[case("sounds/$UNSET/${EMPTY}/low.ogg", vec!["$UNSET", "${EMPTY}"])]
+#[case("sounds/$/low.ogg", vec![])]
+fn unset_variables_are_reported(#[case] raw_path: &str, #[case] expected: Vec<&str>) {
+    assert_eq!(expand_variables(raw_path, fake_variable).1, expected);
+}
+
+#[rstest]
+#[case("battery-alert", "battery-alert".to_string())]
+#[case("~/icons/alert.png", format!("{}/icons/alert.png", home()))]
+#[case("./icons/alert.png", "/etc/spider-volt/./icons/alert.png".to_string())]
+fn only_icon_paths_are_expanded(#[case] raw_icon: &str, #[case] expected: String) {
+    assert_eq!(expand_icon(raw_icon, Path::new("/etc/spider-volt")), expected);
+}
+
+#[rstest]
+fn config_paths_are_expanded() {
+    let config_str = r#"
+    [low_battery_levels.20]
+    message = "Battery Low"
+    notification_icon = "battery-alert"
+    notification_sound = "~/low.ogg"
+
+    [charger_notifications]
+    charging_icon = "icons/charging.png"
+    unplugged_sound = "unplugged.ogg"
+    "#;
+
+    let mut config = Config::parse_toml_from_str(config_str).unwrap();
+    config.expand_paths(Path::new("/config"));
+
+    let low_battery = &config.low_battery_levels.as_ref().unwrap()[&20];
+    assert_eq!(low_battery.notification_icon(), "battery-alert");
+    assert_eq!(low_battery.notification_sound(), format!("{}/low.ogg", home()));
+
+    let charger = config.charger_notifications.as_ref().unwrap();
+    assert_eq!(charger.icon_for_state("Charging"), "/config/icons/charging.png");
+    assert_eq!(charger.sound_for_state("Discharging"), "/config/unplugged.ogg");
+}
+
+#[rstest]
+fn default_and_template_configs_are_valid() {
+    // The default 100% notification has no message, like in earlier versions
+    let issues = Config::default().validate();
+    assert_eq!(issues.len(), 1);
+    assert_eq!((&issues[0].key[..], &issues[0].severity), ("high_battery_levels.100.message", &Severity::Warning));
+    assert!(Config::template().validate().is_empty());
+}
+
+#[rstest]
+fn semantic_errors_are_reported() {
+    let config_str = r#"
+    [low_battery_levels.120]
+    message = "Battery Low"
+
+    [high_battery_levels.90]
+    message = " "
+    "#;
+
+    let issues = Config::parse_toml_from_str(config_str).unwrap().validate();
+    let keys: Vec<(&str, &Severity)> = issues.iter().map(|issue| (issue.key.as_str(), &issue.severity)).collect();
+
+    assert_eq!(keys, vec![
+        ("low_battery_levels.120", &Severity::Error),
+        ("high_battery_levels.90.message", &Severity::Warning),
+        ("low_battery_levels.120", &Severity::Warning),
+    ]);
+}
+
+#[rstest]
+#[case("critical", UrgencyLevel::Critical)]
+#[case("Normal", UrgencyLevel::Normal)]
+#[case("LOW", UrgencyLevel::Low)]
+fn urgency_is_case_insensitive(#[case] urgent_level: &str, #[case] expected: UrgencyLevel) {
+    let config_str = format!("[charger_notifications]\nurgent_level = \"{urgent_level}\"");
+    let config = Config::parse_toml_from_str(&config_str).unwrap();
+    assert_eq!(config.charger_notifications.unwrap().urgent_level(), expected);
+}
+
+#[rstest]
+#[case("urgent")]
+#[case("high")]
+fn invalid_urgency_is_rejected_at_load_time(#[case] urgent_level: &str) {
+    let config_str = format!("[low_battery_levels.20]\nmessage = \"Low\"\nurgent_level = \"{urgent_level}\"");
+    let error = Config::parse_toml_from_str(&config_str).unwrap_err();
+    assert!(error.to_string().contains("unknown variant"));
+}
+
+/// Written by `--create-config` before urgency levels were validated
+const BASELINE_CONFIG: &str = r#"notification_time = 5000
+
+[high_battery_levels.100]
+message = ""
+notification_icon = ""
+notification_sound = ""
+urgent_level = ""
+
+[low_battery_levels.20]
+message = "Battery Low"
+notification_icon = ""
+notification_sound = ""
+urgent_level = ""
+
+[charger_notifications]
+charging = false
+plugged_sound = ""
+charging_icon = ""
+discharging = false
+unplugged_sound = ""
+discharging_icon = ""
+urgent_level = ""
+"#;
+
+#[rstest]
+fn empty_urgency_is_unset() {
+    let mut config = Config::parse_toml_from_str("[defaults]\nurgent_level = \"Critical\"").unwrap();
+    config.layer(Config::parse_toml_from_str(BASELINE_CONFIG).unwrap());
+    config.resolve_inheritance();
+
+    // The empty urgency does not replace the urgency of the lower layer
+    assert_eq!(config.low_battery_levels.unwrap()[&20].urgent_level(), UrgencyLevel::Critical);
+    assert_eq!(config.charger_notifications.unwrap().urgent_level(), UrgencyLevel::Critical);
+}
+
+#[rstest]
+fn baseline_generated_config_still_loads() {
+    let temp_dir = TempDir::new("baseline");
+    let config_path = temp_dir.write("config.toml", BASELINE_CONFIG);
+
+    let config = load_config_chain(std::slice::from_ref(&config_path), None, None).unwrap();
+    assert_eq!(config.low_battery_levels.as_ref().unwrap().keys().collect::<Vec<_>>(), vec![&20]);
+    assert_eq!(config.high_battery_levels.as_ref().unwrap().keys().collect::<Vec<_>>(), vec![&100]);
+    assert_eq!(config.low_battery_levels.as_ref().unwrap()[&20].urgent_level(), UrgencyLevel::Normal);
+    assert!(config.validate().iter().all(|issue| issue.severity == Severity::Warning));
+}
+
+#[rstest]
+fn urgency_is_serialized_as_a_variant_name() {
+    let config = Config::parse_toml_from_str("[charger_notifications]\nurgent_level = \"critical\"").unwrap();
+    let toml = toml::to_string(&config).unwrap();
+    assert!(toml.contains("urgent_level = \"Critical\""));
+}
+
+#[rstest]
+fn schema_accepts_urgency_in_any_case() {
+    let schema = serde_json::to_value(config_schema()).unwrap();
+    let urgency_schema = &schema["$defs"]["UrgencyLevel"];
+    assert!(urgency_schema.get("enum").is_none(), "{urgency_schema}");
+    assert_eq!(urgency_schema["type"], "string");
+    assert_eq!(urgency_schema["pattern"],
+        r"^\s*([Ll][Oo][Ww]|[Nn][Oo][Rr][Mm][Aa][Ll]|[Cc][Rr][Ii][Tt][Ii][Cc][Aa][Ll])\s*$");
+    assert_eq!(schema["$defs"]["ChargerNotification"]["properties"]["urgent_level"]["anyOf"][0]["$ref"], "#/$defs/UrgencyLevel");
+}
+
+#[rstest]
+fn schema_describes_every_top_level_field() {
+    let schema = serde_json::to_value(config_schema()).unwrap();
+    let properties = schema["properties"].as_object().unwrap();
+    let config = serde_json::to_value(Config::template()).unwrap();
+    let mut fields: Vec<&String> = properties.keys().collect();
+    fields.sort();
+    for field in config.as_object().unwrap().keys() {
+        assert!(properties.contains_key(field), "{field} is missing from {fields:?}");
+    }
+    assert_eq!(properties["notification_time"]["description"], "How long notifications are shown for in ms, defaults to 5000");
+}
+
+#[rstest]
+#[case("low_battery_levels.20.message", Some((3, 5)))]
+#[case("low_battery_levels.20.urgent_level", Some((2, 21)))]
+#[case("charger_notifications", None)]
+fn keys_are_located(#[case] key: &str, #[case] expected: Option<(usize, usize)>) {
+    let config_str = "notification_time = 5000\n[low_battery_levels.20]\n    message = \"\"\n";
+    assert_eq!(locate_key(config_str, key), expected);
+}
+
+#[rstest]
+fn reload_keeps_sent_thresholds_that_still_exist() {
+    let config_str = r#"
+    [low_battery_levels.20]
+    message = "Battery Low"
+
+    [high_battery_levels.80]
+    message = "Battery High"
+    "#;
+
+    let mut battery_notif_sent = HashSet::from([10, 20, 80, 90]);
+    Config::parse_toml_from_str(config_str).unwrap().retain_sent_thresholds(&mut battery_notif_sent);
+
+    assert_eq!(battery_notif_sent, HashSet::from([20, 80]));
+}
+
+#[rstest]
+fn config_file_changes_request_a_reload() {
+    let temp_dir = TempDir::new("reload");
+    let config_path = temp_dir.join("config.toml");
+
+    let mut config_reloader = ConfigReloader::new(std::slice::from_ref(&config_path));
+    assert!(!config_reloader.reload_requested());
+
+    // Other files in the same directory are ignored
+    temp_dir.write("other.toml", "");
+    assert!(!config_reloader.reload_requested());
+
+    fs::write(&config_path, "notification_time = 3000").unwrap();
+    assert!(config_reloader.reload_requested());
+    assert!(!config_reloader.reload_requested());
+}
+
+#[rstest]
+#[case(None, vec!["/etc/spider-volt/config.toml", "/etc/xdg/spider-volt/config.toml"])]
+#[case(Some(""), vec!["/etc/spider-volt/config.toml", "/etc/xdg/spider-volt/config.toml"])]
+#[case(Some("/opt/xdg:relative:/usr/share/xdg"), vec![
+    "/etc/spider-volt/config.toml",
+    "/usr/share/xdg/spider-volt/config.toml",
+    "/opt/xdg/spider-volt/config.toml",
+])]
+fn system_config_paths_follow_xdg_config_dirs(#[case] config_dirs: Option<&str>, #[case] expected: Vec<&str>) {
+    let paths = system_config_paths_in(config_dirs.map(str::to_string));
+    assert_eq!(paths, expected.iter().map(PathBuf::from).collect::<Vec<_>>());
+}
+
+#[rstest]
+fn user_config_is_layered_on_top_of_system_config() {
+    let system_config_str = r#"
+    notification_time = 3000
+
+    [low_battery_levels.15]
+    message = "System Low"
+
+    [charger_notifications]
+    charging = true
+    "#;
+    let user_config_str = r#"
+    [low_battery_levels.25]
+    message = "User Low"
+    "#;
+
+    let mut config = Config::parse_toml_from_str(system_config_str).unwrap();
+    config.layer(Config::parse_toml_from_str(user_config_str).unwrap());
+
+    assert_eq!(config.time(), 3000);
+    assert!(config.charger_notifications.unwrap().should_notify_for_state("Charging"));
+    let low_battery_levels = config.low_battery_levels.unwrap();
+    assert_eq!(low_battery_levels.keys().collect::<Vec<_>>(), vec![&15, &25]);
+}
+
+#[rstest]
+fn partial_config_keeps_the_defaults() {
+    let config_str = r#"
+    [charger_notifications]
+    charging = true
+    "#;
+
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str(config_str).unwrap());
+
+    let low_battery_levels = config.low_battery_levels.unwrap();
+    assert_eq!(low_battery_levels[&20].notification_message(), "Battery Low");
+    assert!(config.charger_notifications.is_some());
+}
+
+#[rstest]
+fn thresholds_are_merged_field_by_field() {
+    let config_str = r#"
+    [low_battery_levels.20]
+    notification_sound = "/sounds/low.ogg"
+    "#;
+
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str(config_str).unwrap());
+
+    let low_battery = &config.low_battery_levels.as_ref().unwrap()[&20];
+    assert_eq!(low_battery.notification_message(), "Battery Low");
+    assert_eq!(low_battery.notification_sound(), "/sounds/low.ogg");
+    assert!(config.validate().iter().all(|issue| issue.severity == Severity::Warning));
+}
+
+#[rstest]
+#[case("low_battery_levels = {}", vec![])]
+#[case("[low_battery_levels.20]", vec![])]
+#[case("[low_battery_levels.20]\n[low_battery_levels.10]\nmessage = \"Critical\"", vec![10])]
+fn empty_tables_disable_defaults(#[case] config_str: &str, #[case] expected: Vec<u8>) {
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str(config_str).unwrap());
+
+    let low_battery_levels = config.low_battery_levels.unwrap();
+    assert_eq!(low_battery_levels.into_keys().collect::<Vec<_>>(), expected);
+}
+
+#[rstest]
+fn empty_charger_table_disables_charger_notifications() {
+    let mut config = Config::parse_toml_from_str("[charger_notifications]\ncharging = true").unwrap();
+    config.layer(Config::parse_toml_from_str("charger_notifications = {}").unwrap());
+    assert!(config.charger_notifications.is_none());
+}
+
+#[rstest]
+fn missing_message_is_reported_after_layering() {
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str("[low_battery_levels.10]\nurgent_level = \"Critical\"").unwrap());
+
+    let issues: Vec<_> = config.validate().into_iter().filter(|issue| issue.severity == Severity::Error).collect();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].key, "low_battery_levels.10.message");
+}
+
+#[rstest]
+fn notifications_inherit_from_defaults() {
+    let config_str = r#"
+    [defaults]
+    notification_icon = "battery"
+    notification_sound = "/sounds/default.ogg"
+
+    [defaults.low]
+    notification_icon = "battery-alert"
+    urgent_level = "Critical"
+
+    [low_battery_levels.10]
+    message = "Battery Critically Low"
+    notification_sound = "/sounds/critical.ogg"
+
+    [high_battery_levels.90]
+    message = "Battery Charged"
+
+    [charger_notifications]
+    charging = true
+    charging_icon = "battery-charging"
+    "#;
+
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str(config_str).unwrap());
+    config.resolve_inheritance();
+
+    let low_battery_levels = config.low_battery_levels.as_ref().unwrap();
+    assert_eq!(low_battery_levels[&20].notification_icon(), "battery-alert");
+    assert_eq!(low_battery_levels[&20].notification_sound(), "/sounds/default.ogg");
+    assert_eq!(low_battery_levels[&20].urgent_level(), UrgencyLevel::Critical);
+    assert_eq!(low_battery_levels[&10].notification_sound(), "/sounds/critical.ogg");
+
+    let high_battery = &config.high_battery_levels.as_ref().unwrap()[&90];
+    assert_eq!(high_battery.notification_icon(), "battery");
+    assert_eq!(high_battery.urgent_level(), UrgencyLevel::Normal);
+
+    let charger = config.charger_notifications.as_ref().unwrap();
+    assert_eq!(charger.icon_for_state("Charging"), "battery-charging");
+    assert_eq!(charger.icon_for_state("Discharging"), "battery");
+    assert_eq!(charger.sound_for_state("Charging"), "/sounds/default.ogg");
+}
+
+#[rstest]
+#[case("config.toml", ConfigFormat::Toml)]
+#[case("config.json", ConfigFormat::Json)]
+#[case("config.yaml", ConfigFormat::Yaml)]
+#[case("config.yml", ConfigFormat::Yaml)]
+#[case("config", ConfigFormat::Toml)]
+fn format_is_detected_from_the_extension(#[case] file_name: &str, #[case] expected: ConfigFormat) {
+    assert_eq!(ConfigFormat::from_path(Path::new(file_name)), expected);
+}
+
+#[rstest]
+#[case(ConfigFormat::Json, r#"{"low_battery_levels": {"15": {"message": "Low", "urgent_level": "critical"}}}"#)]
+#[case(ConfigFormat::Yaml, "low_battery_levels:\n  15:\n    message: Low\n    urgent_level: critical\n")]
+fn json_and_yaml_configs_are_parsed(#[case] format: ConfigFormat, #[case] source: &str) {
+    let config = format.parse(source).unwrap();
+    let low_battery = &config.low_battery_levels.as_ref().unwrap()[&15];
+    assert_eq!(low_battery.notification_message(), "Low");
+    assert_eq!(low_battery.urgent_level(), UrgencyLevel::Critical);
+}
+
+#[rstest]
+#[case(ConfigFormat::Toml)]
+#[case(ConfigFormat::Json)]
+#[case(ConfigFormat::Yaml)]
+fn serialized_configs_parse_back(#[case] format: ConfigFormat) {
+    let serialized = format.serialize(&Config::template()).unwrap();
+    assert!(!serialized.contains("null"));
+
+    let config = format.parse(&serialized).unwrap();
+    assert_eq!(config.high_battery_levels.as_ref().unwrap()[&100].notification_message(), "Battery Fully Charged");
+}
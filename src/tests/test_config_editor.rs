@@ -0,0 +1,155 @@
+use std::{fs, os::unix::fs::{symlink, PermissionsExt}, path::PathBuf};
+
+use super::*;
+use crate::config_editor::{config_get, config_set, config_unset};
+use crate::config_reloader::ConfigReloader;
+
+const CONFIG_STR: &str = r#"# header comment
+notification_time = 5000 # shown for 5 seconds
+
+# low battery
+[low_battery_levels.20]
+message = "Battery Low"
+urgent_level = "Critical"
+
+# plugged in
+[charger_notifications]
+charging = true
+"#;
+
+/// A config file in a temp dir, the path is a string like the one passed to --config
+struct TempConfig {
+    temp_dir: TempDir,
+    path: String,
+}
+
+impl TempConfig {
+    fn new(name: &str, config_str: &str) -> Self {
+        let temp_dir = TempDir::new(&format!("edit-{name}"));
+        let path = temp_dir.write("config.toml", config_str).display().to_string();
+        TempConfig { temp_dir, path }
+    }
+
+    fn read(&self) -> String {
+        fs::read_to_string(&self.path).unwrap()
+    }
+}
+
+#[rstest]
+#[case("notification_time", "5000")]
+#[case("low_battery_levels.20.message", "Battery Low")]
+#[case("charger_notifications.charging", "true")]
+#[case("charger_notifications", "charging = true")]
+fn values_are_read(#[case] key: &str, #[case] expected: &str) {
+    let config = TempConfig::new("get", CONFIG_STR);
+    assert_eq!(config_get(&config.path, key).unwrap(), expected);
+}
+
+#[rstest]
+#[case("mute_sounds")]
+#[case("low_battery_levels.10.message")]
+fn missing_values_are_errors(#[case] key: &str) {
+    let config = TempConfig::new("get-missing", CONFIG_STR);
+    assert!(config_get(&config.path, key).is_err());
+}
+
+#[rstest]
+fn set_keeps_comments_and_formatting() {
+    let config = TempConfig::new("set", CONFIG_STR);
+    config_set(&config.path, "notification_time", "3000").unwrap();
+    config_set(&config.path, "low_battery_levels.15.message", "Plug in soon").unwrap();
+
+    // New tables are added next to the tables of the same section
+    let expected = CONFIG_STR.replace("5000 #", "3000 #").replace("\n# plugged in",
+        "\n[low_battery_levels.15]\nmessage = \"Plug in soon\"\n\n# plugged in");
+    assert_eq!(config.read(), expected);
+
+    let parsed = Config::parse_toml_from_str(&config.read()).unwrap();
+    assert_eq!(parsed.time(), 3000);
+}
+
+#[rstest]
+#[case("notification_time", "soon")]
+#[case("low_battery_levels.120.message", "Too high")]
+#[case("low_battery_levels.20.urgent_level", "urgent")]
+#[case("low_battery_levels.20.message.text", "Battery Low")]
+fn invalid_edits_are_not_saved(#[case] key: &str, #[case] value: &str) {
+    let config = TempConfig::new("set-invalid", CONFIG_STR);
+    assert!(config_set(&config.path, key, value).is_err());
+    assert_eq!(config.read(), CONFIG_STR);
+}
+
+#[rstest]
+fn set_edits_a_list_of_inline_tables() {
+    let config = TempConfig::new("set-inline", "profile_rules = [{ profile = \"desk\" }]\n[profiles.desk]\nmute_sounds = true\n");
+    config_set(&config.path, "profile_rules.0.start", "\"09:00\"").unwrap();
+    config_set(&config.path, "profile_rules.0.days", "[\"mon\"]").unwrap();
+
+    assert_eq!(config_get(&config.path, "profile_rules.0.start").unwrap(), "09:00");
+    assert_eq!(config.read(),
+        "profile_rules = [{ profile = \"desk\", start = \"09:00\", days = [\"mon\"] }]\n[profiles.desk]\nmute_sounds = true\n");
+    assert!(config_set(&config.path, "profile_rules.1.start", "09:00").unwrap_err().to_string().contains("has no entry 1"));
+}
+
+#[rstest]
+fn set_creates_a_missing_file() {
+    let config = TempConfig::new("set-missing", "");
+    fs::remove_file(&config.path).unwrap();
+    config_set(&config.path, "charger_notifications.discharging", "true").unwrap();
+    assert_eq!(config.read(), "[charger_notifications]\ndischarging = true\n");
+}
+
+#[rstest]
+fn set_edits_the_target_of_a_symlink_and_keeps_its_permissions() {
+    let config = TempConfig::new("set-symlink", CONFIG_STR);
+    fs::set_permissions(&config.path, fs::Permissions::from_mode(0o640)).unwrap();
+    let link_path = config.temp_dir.join("link.toml");
+    symlink(&config.path, &link_path).unwrap();
+
+    config_set(&link_path.display().to_string(), "notification_time", "3000").unwrap();
+
+    assert!(fs::symlink_metadata(&link_path).unwrap().file_type().is_symlink());
+    assert!(config.read().contains("notification_time = 3000"));
+    assert_eq!(fs::metadata(&config.path).unwrap().permissions().mode() & 0o777, 0o640);
+}
+
+#[rstest]
+fn unset_keeps_the_comments_of_other_keys() {
+    let config = TempConfig::new("unset", CONFIG_STR);
+    config_unset(&config.path, "notification_time").unwrap();
+    config_unset(&config.path, "low_battery_levels.20").unwrap();
+
+    let edited = config.read();
+    assert!(!edited.contains("notification_time"));
+    assert!(!edited.contains("[low_battery_levels.20]"));
+    for comment in ["# header comment", "# low battery", "# plugged in"] {
+        assert!(edited.contains(comment), "{comment} was removed:\n{edited}");
+    }
+}
+
+#[rstest]
+fn unset_removes_tables_left_empty() {
+    // An empty [charger_notifications] would disable charger notifications from lower layers
+    let config = TempConfig::new("unset-empty", CONFIG_STR);
+    config_unset(&config.path, "charger_notifications.charging").unwrap();
+    assert!(!config.read().contains("[charger_notifications]"));
+    assert!(config_unset(&config.path, "charger_notifications.charging").is_err());
+}
+
+#[rstest]
+#[case("config.yaml")]
+#[case("config.json")]
+fn only_toml_files_are_edited(#[case] file_name: &str) {
+    let config_path = env::temp_dir().join(file_name).display().to_string();
+    assert!(config_set(&config_path, "notification_time", "3000").is_err());
+}
+
+#[rstest]
+fn edits_request_a_reload() {
+    let config = TempConfig::new("reload", CONFIG_STR);
+    let mut config_reloader = ConfigReloader::new(&[PathBuf::from(&config.path)]);
+    assert!(!config_reloader.reload_requested());
+
+    config_set(&config.path, "notification_time", "3000").unwrap();
+    assert!(config_reloader.reload_requested());
+}
@@ -0,0 +1,147 @@
+use std::{fs, path::PathBuf};
+
+use super::*;
+use crate::config::Severity;
+use crate::config_file_manager::{check_config_chain, collect_config_layers, config_sources, load_config_chain, ConfigError};
+use crate::config_origins::{annotate_config_origins, config_origins};
+use crate::config_reloader::ConfigReloader;
+
+/// A config directory with a config.toml
+struct TempConfigDir {
+    temp_dir: TempDir,
+}
+
+impl TempConfigDir {
+    fn new(name: &str, config_str: &str) -> Self {
+        let temp_dir = TempDir::new(&format!("include-{name}"));
+        temp_dir.write("config.toml", config_str);
+        TempConfigDir { temp_dir }
+    }
+
+    fn write(&self, relative_path: &str, config_str: &str) -> PathBuf {
+        self.temp_dir.write(relative_path, config_str)
+    }
+
+    fn config_files(&self) -> Vec<PathBuf> {
+        vec![self.temp_dir.join("config.toml")]
+    }
+
+    fn layer_names(&self) -> Vec<String> {
+        collect_config_layers(&self.config_files())
+            .unwrap()
+            .into_iter()
+            .map(|layer| layer.path.strip_prefix(self.temp_dir.path()).unwrap().display().to_string())
+            .collect()
+    }
+}
+
+#[rstest]
+fn includes_are_layered_below_the_including_file() {
+    let config_dir = TempConfigDir::new("order", "include = [\"first.toml\", \"second.toml\"]\nnotification_time = 1000\n");
+    config_dir.write("first.toml", "notification_time = 2000\nannounce_profile_switches = true\n");
+    config_dir.write("second.toml", "notification_time = 3000\nminimum_urgency = \"low\"\n");
+
+    assert_eq!(config_dir.layer_names(), ["first.toml", "second.toml", "config.toml"]);
+    let config = load_config_chain(&config_dir.config_files(), None, None).unwrap();
+    assert_eq!(config.time(), 1000);
+    assert!(config.announce_profile_switches());
+}
+
+#[rstest]
+fn nested_includes_are_relative_to_their_file() {
+    let config_dir = TempConfigDir::new("nested", "include = [\"shared/outer.toml\"]\n");
+    config_dir.write("shared/outer.toml", "include = [\"inner.toml\"]\nnotification_time = 2000\n");
+    config_dir.write("shared/inner.toml", "notification_time = 3000\n");
+
+    assert_eq!(config_dir.layer_names(), ["shared/inner.toml", "shared/outer.toml", "config.toml"]);
+    let config = load_config_chain(&config_dir.config_files(), None, None).unwrap();
+    assert_eq!(config.time(), 2000);
+}
+
+#[rstest]
+#[case("include = [\"config.toml\"]\n", "includes itself")]
+#[case("include = [\"other.toml\"]\n", "includes itself")]
+#[case("include = [\"missing.toml\"]\n", "does not exist")]
+fn broken_includes_are_errors(#[case] config_str: &str, #[case] expected: &str) {
+    let config_dir = TempConfigDir::new("broken", config_str);
+    config_dir.write("other.toml", "include = [\"config.toml\"]\n");
+
+    let error = collect_config_layers(&config_dir.config_files()).err().unwrap();
+    assert!(matches!(error, ConfigError::Include(..)));
+    assert!(error.to_string().contains(expected), "{error}");
+}
+
+#[rstest]
+fn drop_ins_are_layered_in_lexical_order() {
+    let config_dir = TempConfigDir::new("drop-ins", "notification_time = 1000\n");
+    config_dir.write("config.d/20-second.toml", "notification_time = 3000\n");
+    config_dir.write("config.d/10-first.toml", "notification_time = 2000\nannounce_profile_switches = true\n");
+    config_dir.write("config.d/30-backup.toml~", "notification_time = 4000\n");
+    config_dir.write("config.d/README", "not a config file");
+
+    assert_eq!(config_dir.layer_names(), ["config.toml", "config.d/10-first.toml", "config.d/20-second.toml"]);
+    let config = load_config_chain(&config_dir.config_files(), None, None).unwrap();
+    assert_eq!(config.time(), 3000);
+    assert!(config.announce_profile_switches());
+}
+
+#[rstest]
+fn drop_ins_are_used_without_a_config_file() {
+    let config_dir = TempConfigDir::new("drop-ins-only", "");
+    fs::remove_file(config_dir.temp_dir.join("config.toml")).unwrap();
+    config_dir.write("config.d/10-time.toml", "notification_time = 2000\n");
+
+    let config = load_config_chain(&config_dir.config_files(), None, None).unwrap();
+    assert_eq!(config.time(), 2000);
+}
+
+#[rstest]
+fn origins_name_the_file_that_set_each_value() {
+    let config_dir = TempConfigDir::new("origins", "include = [\"sounds.toml\"]\n[low_battery_levels.20]\nurgent_level = \"Critical\"\n");
+    config_dir.write("sounds.toml", "[low_battery_levels.10]\nmessage = \"Critically Low\"\n");
+    let drop_in = config_dir.write("config.d/10-time.toml", "notification_time = 2000\n");
+
+    let config = load_config_chain(&config_dir.config_files(), None, None).unwrap();
+    let origins = config_origins(&config_dir.config_files(), None, &config).unwrap();
+    assert_eq!(origins["notification_time"], drop_in.display().to_string());
+    assert_eq!(origins["low_battery_levels.10.message"], config_dir.temp_dir.join("sounds.toml").display().to_string());
+    assert_eq!(origins["low_battery_levels.20.message"], "built-in default");
+
+    let annotated_config = annotate_config_origins(&config, &origins).unwrap();
+    assert!(annotated_config.contains(&format!("notification_time = 2000 # {}", drop_in.display())));
+}
+
+#[rstest]
+fn check_layers_includes_and_drop_ins() {
+    let config_dir = TempConfigDir::new("check", "include = [\"messages.toml\"]\n[low_battery_levels.15]\nnotification_icon = \"battery-low\"\n");
+    config_dir.write("messages.toml", "[low_battery_levels.15]\nmessage = \"Low\"\n");
+    config_dir.write("config.d/critical.toml", "[low_battery_levels.5]\nnotification_icon = \"battery-caution\"\n");
+
+    let issues = check_config_chain(&config_dir.config_files()).unwrap();
+    let errors: Vec<_> = issues.iter().filter(|(issue, _)| issue.severity == Severity::Error).collect();
+    // The message of 15% comes from the included file, only 5% is missing one
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    let (issue, location) = errors[0];
+    assert_eq!(issue.key, "low_battery_levels.5.message");
+    let expected_location = format!("{}:1:21", config_dir.temp_dir.join("config.d/critical.toml").display());
+    assert_eq!(location.as_deref(), Some(expected_location.as_str()));
+}
+
+#[rstest]
+fn included_files_and_drop_ins_are_watched() {
+    let config_dir = TempConfigDir::new("watch", "include = [\"shared/sounds.toml\"]\n");
+    let included_file = config_dir.write("shared/sounds.toml", "announce_profile_switches = true\n");
+    fs::create_dir_all(config_dir.temp_dir.join("config.d")).unwrap();
+
+    let sources = config_sources(&config_dir.config_files());
+    assert!(sources.files.contains(&included_file));
+    let mut config_reloader = ConfigReloader::new(&config_dir.config_files());
+    config_reloader.watch(&sources.files, &sources.directories);
+    assert!(!config_reloader.reload_requested());
+
+    config_dir.write("shared/sounds.toml", "mute_sounds = false\n");
+    assert!(config_reloader.reload_requested());
+
+    config_dir.write("config.d/10-time.toml", "notification_time = 2000\n");
+    assert!(config_reloader.reload_requested());
+}
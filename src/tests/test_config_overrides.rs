@@ -0,0 +1,115 @@
+use std::{ffi::{OsStr, OsString}, os::unix::ffi::OsStrExt};
+
+use super::*;
+use crate::config::{GetUrgency, UrgencyLevel};
+use crate::config_file_manager::load_config_chain;
+use crate::config_overrides::{config_overrides, parse_threshold_message};
+
+fn env_vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (OsString, OsString)> {
+    vars.iter()
+        .map(|(name, value)| (OsString::from(name), OsString::from(value)))
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[rstest]
+#[case("15:Battery low", Ok((15, "Battery low".to_string())))]
+#[case("80%:Unplug: now", Ok((80, "Unplug: now".to_string())))]
+#[case("101:Too high", Err("\"101\" is not a percentage between 0 and 100".to_string()))]
+#[case("15", Err("expected THRESHOLD:MESSAGE, e.g. 15:\"Battery low\"".to_string()))]
+#[case("15: ", Err("the message cannot be empty".to_string()))]
+fn threshold_messages_are_parsed(#[case] value: &str, #[case] expected: Result<(u8, String), String>) {
+    assert_eq!(parse_threshold_message(value), expected);
+}
+
+#[rstest]
+fn variables_that_are_not_utf8_are_skipped() {
+    let not_utf8 = OsStr::from_bytes(b"\xff").to_os_string();
+    let vars = [
+        (OsString::from("JUNK"), not_utf8.clone()),
+        (not_utf8.clone(), OsString::from("value")),
+        (OsString::from("SPIDER_VOLT_NOTIFICATION_TIME"), not_utf8),
+        (OsString::from("SPIDER_VOLT_LOW_BATTERY_LEVELS__15__MESSAGE"), OsString::from("Battery low")),
+    ];
+    let overrides = config_overrides(vars.into_iter(), &[], &[]).unwrap().unwrap();
+    assert_eq!(overrides.time(), 5000);
+    assert!(overrides.low_battery_levels.unwrap().contains_key(&15));
+}
+
+#[rstest]
+fn no_overrides_is_none() {
+    let overrides = config_overrides(env_vars(&[("HOME", "/home/user"), ("SPIDER_VOLT_", "")]), &[], &[]);
+    assert!(overrides.unwrap().is_none());
+}
+
+#[rstest]
+fn environment_variables_override_nested_values() {
+    let overrides = config_overrides(env_vars(&[
+        ("SPIDER_VOLT_NOTIFICATION_TIME", "3000"),
+        ("SPIDER_VOLT_LOW_BATTERY_LEVELS__20__URGENT_LEVEL", "critical"),
+        ("SPIDER_VOLT_CHARGER_NOTIFICATIONS__CHARGING", "true"),
+    ]), &[], &[]).unwrap().unwrap();
+
+    let mut config = Config::default();
+    config.layer(overrides);
+    assert_eq!(config.time(), 3000);
+    let low_battery = &config.low_battery_levels.as_ref().unwrap()[&20];
+    assert_eq!(low_battery.notification_message(), "Battery Low");
+    assert_eq!(low_battery.urgent_level(), UrgencyLevel::Critical);
+    assert!(config.charger_notifications.as_ref().unwrap().should_notify_for_state("Charging"));
+}
+
+#[rstest]
+fn flags_take_priority_over_the_environment() {
+    let overrides = config_overrides(
+        env_vars(&[("SPIDER_VOLT_LOW_BATTERY_LEVELS__15__MESSAGE", "From the environment")]),
+        &[(15, "From the flag".to_string())],
+        &[(80, "Unplug now".to_string())],
+    ).unwrap().unwrap();
+
+    assert_eq!(overrides.low_battery_levels.as_ref().unwrap()[&15].notification_message(), "From the flag");
+    assert_eq!(overrides.high_battery_levels.as_ref().unwrap()[&80].notification_message(), "Unplug now");
+}
+
+#[rstest]
+#[case("42")]
+#[case("true")]
+#[case("2026-10-19")]
+fn values_that_do_not_fit_the_key_are_strings(#[case] message: &str) {
+    let overrides = config_overrides(env_vars(&[
+        ("SPIDER_VOLT_LOW_BATTERY_LEVELS__20__MESSAGE", message),
+        ("SPIDER_VOLT_NOTIFICATION_TIME", "3000"),
+    ]), &[], &[]).unwrap().unwrap();
+
+    assert_eq!(overrides.low_battery_levels.as_ref().unwrap()[&20].notification_message(), message);
+    assert_eq!(overrides.time(), 3000);
+}
+
+#[rstest]
+fn profile_names_keep_their_case() {
+    let overrides = config_overrides(env_vars(&[("SPIDER_VOLT_PROFILES__Desk__NOTIFICATION_TIME", "8000")]), &[], &[])
+        .unwrap().unwrap();
+
+    assert_eq!(overrides.profile_names().collect::<Vec<_>>(), ["Desk"]);
+    assert_eq!(overrides.profile("Desk").unwrap().time(), 8000);
+}
+
+#[rstest]
+fn invalid_overrides_are_errors() {
+    assert!(config_overrides(env_vars(&[("SPIDER_VOLT_NOTIFICATION_TIME", "soon")]), &[], &[]).is_err());
+}
+
+#[rstest]
+fn overrides_take_priority_over_files_and_profiles() {
+    let temp_dir = TempDir::new("overrides");
+    let config_path = temp_dir.write("config.toml", "notification_time = 1000\n[profiles.slow]\nnotification_time = 8000\n");
+
+    let overrides = config_overrides(env_vars(&[("SPIDER_VOLT_NOTIFICATION_TIME", "3000")]), &[], &[])
+        .unwrap();
+    let config = load_config_chain(std::slice::from_ref(&config_path), overrides.as_ref(), Some("slow")).unwrap();
+    assert_eq!(config.active_profile(), Some("slow"));
+    assert_eq!(config.time(), 3000);
+
+    let config = load_config_chain(std::slice::from_ref(&config_path), None, Some("slow")).unwrap();
+    assert_eq!(config.time(), 8000);
+}
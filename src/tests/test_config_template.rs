@@ -0,0 +1,108 @@
+use std::fs;
+
+use super::*;
+use crate::config::{GetUrgency, Severity, UrgencyLevel};
+use crate::config_file_manager::{create_default_config_file, ConfigFormat};
+use crate::config_template::config_template;
+
+fn toml_template() -> String {
+    config_template(ConfigFormat::Toml).unwrap()
+}
+
+#[rstest]
+fn annotated_template_matches_the_config_template() {
+    let config = Config::parse_toml_from_str(&toml_template()).unwrap();
+    assert!(config.validate().is_empty());
+    assert_eq!(
+        toml::to_string(&config).unwrap(),
+        toml::to_string(&Config::template()).unwrap()
+    );
+}
+
+#[rstest]
+fn uncommented_examples_are_valid() {
+    // Examples are commented out with "#" and no space, explanations use "# "
+    let uncommented: String = toml_template()
+        .lines()
+        .map(|line| match line.strip_prefix('#') {
+            Some(example) if !example.starts_with(' ') => example,
+            _ => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let config = Config::parse_toml_from_str(&uncommented).unwrap();
+    let errors: Vec<_> = config.validate()
+        .into_iter()
+        .filter(|issue| issue.severity == Severity::Error)
+        .collect();
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(config.profile_names().collect::<Vec<_>>(), vec!["quiet"]);
+    assert_eq!(config.profile_rules().len(), 1);
+    assert!(config.low_battery_levels.as_ref().unwrap().contains_key(&10));
+}
+
+#[rstest]
+fn template_lists_the_urgency_levels() {
+    assert!(toml_template().contains(r#"one of "Low", "Normal", "Critical""#));
+    assert!(!toml_template().contains("{urgency_levels}"));
+}
+
+#[rstest]
+#[case(ConfigFormat::Json)]
+#[case(ConfigFormat::Yaml)]
+fn json_and_yaml_templates_parse(#[case] format: ConfigFormat) {
+    let config = format.parse(&config_template(format).unwrap()).unwrap();
+    assert!(config.validate().is_empty());
+}
+
+#[rstest]
+fn template_does_not_override_lower_layers() {
+    let system_config = Config::parse_toml_from_str(r#"
+    [defaults]
+    urgent_level = "Low"
+
+    [charger_notifications]
+    charging = true
+    "#).unwrap();
+    let mut config = Config::default();
+    config.layer(system_config);
+    config.layer(Config::parse_toml_from_str(&toml_template()).unwrap());
+    config.resolve_inheritance();
+
+    assert_eq!(config.time(), 5000);
+    assert_eq!(config.low_battery_levels.as_ref().unwrap()[&20].urgent_level(), UrgencyLevel::Low);
+    assert!(config.charger_notifications.as_ref().unwrap().should_notify_for_state("Charging"));
+}
+
+#[rstest]
+fn uncommented_defaults_example_applies() {
+    let template = toml_template().replace("#[defaults.low]\n#urgent_level = \"Critical\"", "[defaults.low]\nurgent_level = \"Critical\"");
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str(&template).unwrap());
+    config.resolve_inheritance();
+
+    assert_eq!(config.low_battery_levels.as_ref().unwrap()[&20].urgent_level(), UrgencyLevel::Critical);
+}
+
+#[rstest]
+fn force_overwrites_and_keeps_a_backup() {
+    let temp_dir = TempDir::new("create");
+    let config_path = temp_dir.write("config.toml", "notification_time = 1000\n");
+    let backup_path = temp_dir.join("config.toml.bak");
+
+    // Without force the existing file is kept
+    create_default_config_file(config_path.to_str().unwrap(), None, false);
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), "notification_time = 1000\n");
+    assert!(!backup_path.exists());
+
+    create_default_config_file(config_path.to_str().unwrap(), None, true);
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), toml_template());
+    assert_eq!(fs::read_to_string(&backup_path).unwrap(), "notification_time = 1000\n");
+
+    // A second --force keeps the first backup
+    fs::write(&config_path, "notification_time = 2000\n").unwrap();
+    create_default_config_file(config_path.to_str().unwrap(), None, true);
+    assert_eq!(fs::read_to_string(&backup_path).unwrap(), "notification_time = 1000\n");
+    assert_eq!(fs::read_to_string(temp_dir.join("config.toml.bak.1")).unwrap(), "notification_time = 2000\n");
+}
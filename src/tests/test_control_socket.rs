@@ -0,0 +1,155 @@
+use std::{io::{BufRead, BufReader, Write}, os::unix::net::{UnixListener, UnixStream}, thread, time::{Duration, Instant}};
+
+use super::*;
+use crate::control_socket::{parse_request, send_request, ControlError, ControlSocket, DaemonStatus, Request, Response, PROTOCOL_VERSION};
+
+/// Answers requests like the daemon loop does, until `request_count` requests were answered
+fn serve(mut control_socket: ControlSocket, request_count: usize, respond: fn(Request) -> Response) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut answered = 0;
+        while answered < request_count {
+            for (request, connection) in control_socket.accept() {
+                connection.reply(respond(request));
+                answered += 1;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    })
+}
+
+#[rstest]
+#[case(r#"{"version": 1, "command": "status"}"#, Request::Status)]
+#[case(r#"{"version": 1, "command": "reload"}"#, Request::Reload)]
+#[case(r#"{"version": 1, "command": "snooze", "minutes": 30}"#, Request::Snooze { minutes: 30 })]
+#[case(r#"{"command": "switch_profile", "profile": "quiet", "version": 1}"#, Request::SwitchProfile { profile: "quiet".to_string() })]
+#[case(r#"{"version": 1, "command": "test_notification"}"#, Request::TestNotification)]
+#[case(r#"{"version": 1, "command": "quit"}"#, Request::Quit)]
+fn requests_are_parsed(#[case] line: &str, #[case] expected: Request) {
+    assert_eq!(parse_request(line).unwrap(), expected);
+}
+
+#[rstest]
+#[case(r#"{"command": "status"}"#, "no \"version\"")]
+#[case(r#"{"version": 2, "command": "status"}"#, "unsupported protocol version 2")]
+#[case(r#"{"version": 1, "command": "dance"}"#, "invalid request")]
+#[case(r#"{"version": 1, "command": "snooze"}"#, "invalid request")]
+#[case("status", "not valid JSON")]
+fn invalid_requests_are_errors(#[case] line: &str, #[case] expected: &str) {
+    let error = parse_request(line).unwrap_err();
+    assert!(error.contains(expected), "{error}");
+}
+
+#[rstest]
+fn requests_are_answered() {
+    let socket_dir = TempDir::new("socket-answer");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    let server = serve(ControlSocket::bind(&socket_path).unwrap(), 2, |request| match request {
+        Request::Status => Response::status(DaemonStatus {
+            percentage: 42,
+            state: "Discharging".to_string(),
+            time_remaining: Some(3600),
+            profile: "default".to_string(),
+            fired_thresholds: vec![20],
+            snoozed_until: None,
+        }),
+        _ => Response::error("only status is supported"),
+    });
+
+    let response = send_request(&socket_path, &Request::Status).unwrap();
+    assert_eq!(response.version, PROTOCOL_VERSION);
+    let status = response.status.unwrap();
+    assert_eq!((status.percentage, status.fired_thresholds), (42, vec![20]));
+
+    let error = send_request(&socket_path, &Request::Quit).unwrap_err();
+    assert!(matches!(error, ControlError::Refused(message) if message == "only status is supported"));
+    server.join().unwrap();
+}
+
+#[rstest]
+fn silent_clients_do_not_hold_up_other_requests() {
+    let socket_dir = TempDir::new("socket-silent");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    let mut control_socket = ControlSocket::bind(&socket_path).unwrap();
+    let _silent_client = UnixStream::connect(&socket_path).unwrap();
+    let mut client = UnixStream::connect(&socket_path).unwrap();
+    writeln!(client, r#"{{"version": 1, "command": "status"}}"#).unwrap();
+
+    let started = Instant::now();
+    let requests = control_socket.accept();
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert_eq!(requests.into_iter().map(|(request, _)| request).collect::<Vec<_>>(), [Request::Status]);
+}
+
+#[rstest]
+fn incomplete_requests_are_answered_with_an_error_after_a_second() {
+    let socket_dir = TempDir::new("socket-timeout");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    let mut control_socket = ControlSocket::bind(&socket_path).unwrap();
+    let mut client = UnixStream::connect(&socket_path).unwrap();
+    write!(client, r#"{{"version": 1"#).unwrap();
+
+    assert!(control_socket.accept().is_empty());
+    thread::sleep(Duration::from_millis(1100));
+    assert!(control_socket.accept().is_empty());
+    let mut response = String::new();
+    BufReader::new(&client).read_line(&mut response).unwrap();
+    assert!(response.contains("no complete request within 1s"), "{response}");
+}
+
+#[rstest]
+fn requests_sent_in_pieces_are_completed_over_several_polls() {
+    let socket_dir = TempDir::new("socket-pieces");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    let mut control_socket = ControlSocket::bind(&socket_path).unwrap();
+    let mut client = UnixStream::connect(&socket_path).unwrap();
+
+    write!(client, r#"{{"version": 1, "#).unwrap();
+    assert!(control_socket.accept().is_empty());
+    writeln!(client, r#""command": "reload"}}"#).unwrap();
+    let requests = control_socket.accept();
+    assert_eq!(requests.into_iter().map(|(request, _)| request).collect::<Vec<_>>(), [Request::Reload]);
+}
+
+#[rstest]
+fn the_socket_is_removed_when_dropped() {
+    let socket_dir = TempDir::new("socket-drop");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    let control_socket = ControlSocket::bind(&socket_path).unwrap();
+    assert!(socket_path.exists());
+    drop(control_socket);
+    assert!(!socket_path.exists());
+    assert!(matches!(send_request(&socket_path, &Request::Status), Err(ControlError::NotRunning(_))));
+}
+
+#[rstest]
+fn stale_sockets_are_replaced() {
+    let socket_dir = TempDir::new("socket-stale");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    // Dropping a std listener leaves the socket file behind, like a daemon that crashed
+    drop(UnixListener::bind(&socket_path).unwrap());
+    assert!(socket_path.exists());
+
+    let control_socket = ControlSocket::bind(&socket_path).unwrap();
+    assert!(matches!(ControlSocket::bind(&socket_path), Err(ControlError::AlreadyRunning(_))));
+    drop(control_socket);
+}
+
+#[rstest]
+#[case(Some("[low_battery_levels.20]\nmessage = \"Low\"\n[low_battery_levels.10]\nmessage = \"Very low\"\n"), "35")]
+#[case(None, "35")]
+fn test_notifications_are_sent_at_the_current_level(
+        _clear_calls: (),
+        #[case] config_str: Option<&str>,
+        #[case] expected: &str
+    ) {
+    let battery_stats = BatteryStats::parse_toml_from_str(r#"
+        prev_state = "Discharging"
+        last_notified_state = "Discharging"
+        current_state = "Discharging"
+        percentage = 35
+    "#).unwrap();
+    let low_level_notifs = config_str.and_then(|config_str| Config::parse_toml_from_str(config_str).unwrap().low_battery_levels);
+
+    battery_stats.send_test_notification(&low_level_notifs, 5000);
+    assert_eq!(get_calls(), [expected]);
+}
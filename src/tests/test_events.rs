@@ -0,0 +1,95 @@
+use std::{collections::HashSet, io::{BufRead, BufReader, Write}, os::unix::net::UnixListener, thread};
+
+use super::*;
+use crate::control_socket::{watch_events, ControlError};
+use crate::events::{DaemonEvent, NotificationKind};
+
+fn battery_stats(prev_state: &str, current_state: &str, percentage: i32) -> BatteryStats {
+    BatteryStats::parse_toml_from_str(&format!(r#"
+        prev_state = "{prev_state}"
+        last_notified_state = "{prev_state}"
+        current_state = "{current_state}"
+        percentage = {percentage}
+    "#)).unwrap()
+}
+
+#[rstest]
+fn crossing_a_threshold_records_the_notification(_clear_calls: ()) {
+    let config = Config::parse_toml_from_str(r#"
+        [low_battery_levels.20]
+        message = "Battery Low"
+        [high_battery_levels.80]
+        message = "Unplug"
+    "#).unwrap();
+    let mut battery = battery_stats("Discharging", "Discharging", 18);
+    let mut battery_notif_sent = HashSet::new();
+
+    battery.handle_battery(&config.low_battery_levels, &config.high_battery_levels, 5000, &mut battery_notif_sent);
+    battery.handle_battery(&config.low_battery_levels, &config.high_battery_levels, 5000, &mut battery_notif_sent);
+
+    assert_eq!(get_calls(), ["18"]);
+    assert_eq!(battery.take_events(), [
+        DaemonEvent::ThresholdCrossed { kind: NotificationKind::Low, threshold: 20, percentage: 18 },
+        DaemonEvent::NotificationSent { kind: NotificationKind::Low, summary: "Battery Low".to_string(), percentage: 18 },
+    ]);
+    assert!(battery.take_events().is_empty());
+}
+
+#[rstest]
+#[case("charging = true", true)]
+#[case("charging = false", false)]
+fn state_changes_are_recorded_with_or_without_a_notification(
+        _clear_calls: (),
+        #[case] charger_str: &str,
+        #[case] notified: bool
+    ) {
+    let charger = Config::parse_toml_from_str(&format!("[charger_notifications]\n{charger_str}\n")).unwrap().charger_notifications;
+    let mut battery = battery_stats("Discharging", "Charging", 50);
+
+    battery.handle_charger_notifications(&charger, 5000);
+
+    let mut expected = vec![DaemonEvent::StateChanged {
+        state: "Charging".to_string(),
+        previous_state: "Discharging".to_string(),
+        percentage: 50,
+    }];
+    if notified {
+        expected.push(DaemonEvent::NotificationSent { kind: NotificationKind::Charger, summary: "Charging".to_string(), percentage: 50 });
+    }
+    assert_eq!(battery.take_events(), expected);
+}
+
+#[rstest]
+#[case(DaemonEvent::PercentageChanged { percentage: 41, previous_percentage: 42 },
+    r#"{"event":"percentage_changed","percentage":41,"previous_percentage":42}"#)]
+#[case(DaemonEvent::ThresholdCrossed { kind: NotificationKind::High, threshold: 80, percentage: 80 },
+    r#"{"event":"threshold_crossed","kind":"high","threshold":80,"percentage":80}"#)]
+#[case(DaemonEvent::Status(crate::status::battery_status(&battery_stats("Discharging", "Discharging", 21), "default", &HashSet::new(), None)),
+    r#"{"event":"status","percentage":21,"state":"Discharging","time_remaining":null,"profile":"default","fired_thresholds":[],"snoozed_until":null}"#)]
+#[case(DaemonEvent::ConfigReloaded { profile: "quiet".to_string(), error: None },
+    r#"{"event":"config_reloaded","profile":"quiet","error":null}"#)]
+fn events_are_tagged(#[case] event: DaemonEvent, #[case] expected: &str) {
+    assert_eq!(serde_json::to_string(&event).unwrap(), expected);
+}
+
+#[rstest]
+fn watching_streams_the_lines_after_the_response() {
+    let socket_dir = TempDir::new("watch");
+    let socket_path = socket_dir.join("spider-volt.sock");
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let daemon = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request).unwrap();
+        assert!(request.contains(r#""command":"watch""#), "{request}");
+        writeln!(stream, r#"{{"version":1,"ok":true}}"#).unwrap();
+        writeln!(stream, r#"{{"event":"percentage_changed","percentage":41,"previous_percentage":42}}"#).unwrap();
+    });
+
+    let events: Vec<String> = watch_events(&socket_path).unwrap().map(Result::unwrap).collect();
+    daemon.join().unwrap();
+
+    assert_eq!(events, [r#"{"event":"percentage_changed","percentage":41,"previous_percentage":42}"#]);
+    assert!(matches!(watch_events(&socket_path), Err(ControlError::NotRunning(_))));
+}
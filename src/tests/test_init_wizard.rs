@@ -0,0 +1,131 @@
+use std::{collections::VecDeque, path::Path};
+
+use super::*;
+use crate::config_file_manager::parse_config_file;
+use crate::init_wizard::{ask_config, parse_thresholds, run_init_wizard, Prompt};
+
+/// Answers questions from a script and keeps everything that was shown
+struct ScriptedPrompt {
+    answers: VecDeque<&'static str>,
+    shown: Vec<String>,
+}
+
+impl ScriptedPrompt {
+    fn new(answers: &[&'static str]) -> Self {
+        ScriptedPrompt { answers: answers.iter().copied().collect(), shown: Vec::new() }
+    }
+}
+
+impl Prompt for ScriptedPrompt {
+    fn ask(&mut self, question: &str) -> Option<String> {
+        self.shown.push(question.to_string());
+        self.answers.pop_front().map(str::to_string)
+    }
+
+    fn say(&mut self, message: &str) {
+        self.shown.push(message.to_string());
+    }
+}
+
+#[rstest]
+#[case("20, 10", Ok(vec![10, 20]))]
+#[case("15 5 15%", Ok(vec![5, 15]))]
+#[case("", Ok(vec![]))]
+#[case("101", Err("\"101\" is not a percentage between 0 and 100".to_string()))]
+#[case("low", Err("\"low\" is not a percentage between 0 and 100".to_string()))]
+fn thresholds_are_parsed(#[case] answer: &str, #[case] expected: Result<Vec<u8>, String>) {
+    assert_eq!(parse_thresholds(answer), expected);
+}
+
+#[rstest]
+fn defaults_are_used_for_empty_answers(_clear_calls: ()) {
+    // thresholds, icon, sound, preview, high thresholds, plugged, unplugged
+    let mut prompt = ScriptedPrompt::new(&["", "", "", "n", "", "", ""]);
+    let config = ask_config(&mut prompt, Path::new("/")).unwrap();
+
+    let low_battery_levels = config.low_battery_levels.as_ref().unwrap();
+    assert_eq!(low_battery_levels.keys().copied().collect::<Vec<_>>(), vec![10, 20]);
+    assert_eq!(low_battery_levels[&10].notification_icon(), "battery-low");
+    assert_eq!(low_battery_levels[&10].notification_sound(), "");
+    assert!(config.high_battery_levels.as_ref().unwrap().is_empty());
+    assert!(config.charger_notifications.is_none());
+    assert!(config.validate().is_empty());
+    assert!(get_calls().is_empty());
+}
+
+#[rstest]
+fn previews_are_sent_for_every_notification(_clear_calls: ()) {
+    let mut prompt = ScriptedPrompt::new(&[
+        "15", "", "", "y",
+        "90", "", "", "y",
+        "y", "", "", "y",
+        "y", "", "", "",
+    ]);
+    let config = ask_config(&mut prompt, Path::new("/")).unwrap();
+
+    assert_eq!(get_calls(), vec!["15", "90", "Charging", "Discharging"]);
+    let charger = config.charger_notifications.as_ref().unwrap();
+    assert!(charger.should_notify_for_state("Charging"));
+    assert!(charger.should_notify_for_state("Discharging"));
+    assert_eq!(charger.icon_for_state("Discharging"), "battery-discharging");
+}
+
+#[rstest]
+fn invalid_answers_are_asked_again(_clear_calls: ()) {
+    let mut prompt = ScriptedPrompt::new(&[
+        "abc", "none",
+        "none",
+        "maybe", "n",
+        "n",
+    ]);
+    let config = ask_config(&mut prompt, Path::new("/")).unwrap();
+
+    assert!(prompt.shown.contains(&"\"abc\" is not a percentage between 0 and 100".to_string()));
+    assert!(prompt.shown.contains(&"Please answer y or n".to_string()));
+    // "none" disables the default 20% notification instead of keeping it
+    assert!(config.low_battery_levels.as_ref().unwrap().is_empty());
+}
+
+#[rstest]
+fn missing_sound_files_are_asked_again(_clear_calls: ()) {
+    let mut prompt = ScriptedPrompt::new(&["20", "", "missing.ogg", "/", "none", "n", "", "", ""]);
+    ask_config(&mut prompt, Path::new("/")).unwrap();
+
+    assert!(prompt.shown.contains(&"missing.ogg was not found".to_string()));
+    assert!(prompt.shown.contains(&"/ was not found".to_string()));
+}
+
+#[rstest]
+fn closed_input_aborts_without_writing(_clear_calls: ()) {
+    let temp_dir = TempDir::new("init-closed");
+    let config_path = temp_dir.join("config.toml");
+    let mut prompt = ScriptedPrompt::new(&["20"]);
+
+    assert!(!run_init_wizard(&mut prompt, config_path.to_str().unwrap(), false));
+    assert!(!config_path.exists());
+}
+
+#[rstest]
+#[case("config.toml")]
+#[case("config.yaml")]
+#[case("config.json")]
+fn wizard_writes_a_valid_config(_clear_calls: (), #[case] file_name: &str) {
+    let temp_dir = TempDir::new("init");
+    let config_path = temp_dir.join(file_name);
+    temp_dir.write("low.ogg", "");
+
+    // The sound is relative to the config file, like in the config itself
+    let mut prompt = ScriptedPrompt::new(&["25", "", "low.ogg", "n", "none", "y", "", "", "n", ""]);
+    assert!(run_init_wizard(&mut prompt, config_path.to_str().unwrap(), false));
+
+    let config = parse_config_file(&config_path).unwrap();
+    assert!(config.validate().is_empty());
+    assert_eq!(config.low_battery_levels.as_ref().unwrap()[&25].notification_sound(), "low.ogg");
+
+    // An existing file is only replaced with force, and kept as a backup like with --create-config --force
+    let mut prompt = ScriptedPrompt::new(&[]);
+    assert!(!run_init_wizard(&mut prompt, config_path.to_str().unwrap(), false));
+    let mut prompt = ScriptedPrompt::new(&["25", "", "low.ogg", "n", "none", "y", "", "", "n", ""]);
+    assert!(run_init_wizard(&mut prompt, config_path.to_str().unwrap(), true));
+    assert!(temp_dir.join(&format!("{file_name}.bak")).exists());
+}
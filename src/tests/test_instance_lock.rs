@@ -0,0 +1,94 @@
+use std::{fs, path::PathBuf, thread, time::Duration};
+
+use super::*;
+use crate::control_socket::{ControlSocket, Request, Response};
+use crate::instance_lock::{InstanceLock, LockError};
+
+/// A lock and socket path in a directory that does not exist yet, like a fresh $XDG_RUNTIME_DIR
+struct TempLockDir {
+    _temp_dir: TempDir,
+    lock_path: PathBuf,
+    socket_path: PathBuf,
+}
+
+impl TempLockDir {
+    fn new(name: &str) -> Self {
+        let temp_dir = TempDir::new(&format!("lock-{name}"));
+        TempLockDir {
+            lock_path: temp_dir.join("run/spider-volt.lock"),
+            socket_path: temp_dir.join("run/spider-volt.sock"),
+            _temp_dir: temp_dir,
+        }
+    }
+}
+
+#[rstest]
+fn a_second_instance_is_refused_until_the_first_exits() {
+    let temp_dir = TempLockDir::new("second");
+    let first_instance = InstanceLock::acquire(&temp_dir.lock_path).unwrap();
+    assert_eq!(fs::read_to_string(&temp_dir.lock_path).unwrap(), format!("{}\n", std::process::id()));
+
+    let error = InstanceLock::acquire(&temp_dir.lock_path).unwrap_err();
+    assert!(matches!(error, LockError::AlreadyRunning(Some(pid)) if pid == std::process::id()), "{error:?}");
+    let message = error.to_string();
+    assert!(message.contains("is already running") && message.contains("--replace"), "{message}");
+
+    drop(first_instance);
+    assert!(InstanceLock::acquire(&temp_dir.lock_path).is_ok());
+}
+
+#[rstest]
+fn replacing_asks_the_running_instance_to_quit() {
+    let temp_dir = TempLockDir::new("replace");
+    let first_instance = InstanceLock::acquire(&temp_dir.lock_path).unwrap();
+    let mut control_socket = ControlSocket::bind(&temp_dir.socket_path).unwrap();
+
+    // Quits like the daemon loop does, the lock is released when the instance exits
+    let daemon = thread::spawn(move || {
+        loop {
+            for (request, connection) in control_socket.accept() {
+                if request == Request::Quit {
+                    connection.reply(Response::ok());
+                    drop(first_instance);
+                    return;
+                }
+                connection.reply(Response::error("unexpected request"));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    });
+
+    let replaced = InstanceLock::replace(&temp_dir.lock_path, Some(&temp_dir.socket_path), Duration::from_secs(5));
+    daemon.join().unwrap();
+    assert!(replaced.is_ok(), "{replaced:?}");
+    assert!(matches!(InstanceLock::acquire(&temp_dir.lock_path), Err(LockError::AlreadyRunning(_))));
+}
+
+#[rstest]
+fn replacing_gives_up_if_the_instance_keeps_running() {
+    let temp_dir = TempLockDir::new("stuck");
+    let _first_instance = InstanceLock::acquire(&temp_dir.lock_path).unwrap();
+    // Our own pid is in the lock file, so no SIGTERM is sent without a socket to ask through
+    fs::write(&temp_dir.lock_path, "not a pid\n").unwrap();
+
+    let error = InstanceLock::replace(&temp_dir.lock_path, None, Duration::from_millis(200)).unwrap_err();
+    assert!(matches!(error, LockError::StillRunning(None)), "{error:?}");
+}
+
+#[rstest]
+fn replacing_reports_a_pid_without_a_process() {
+    let temp_dir = TempLockDir::new("missing");
+    let _first_instance = InstanceLock::acquire(&temp_dir.lock_path).unwrap();
+    // Above any pid_max, so no process can have it
+    fs::write(&temp_dir.lock_path, "2147483646\n").unwrap();
+
+    let error = InstanceLock::replace(&temp_dir.lock_path, None, Duration::from_millis(200)).unwrap_err();
+    assert!(matches!(&error, LockError::Signal(2147483646, _)), "{error:?}");
+    assert!(error.to_string().contains("no process has that pid"), "{error}");
+}
+
+#[rstest]
+fn replacing_without_a_running_instance_just_locks() {
+    let temp_dir = TempLockDir::new("none");
+    assert!(InstanceLock::replace(&temp_dir.lock_path, Some(&temp_dir.socket_path), Duration::from_millis(200)).is_ok());
+}
@@ -0,0 +1,79 @@
+use chrono::{Local, TimeZone};
+use log::{Level, LevelFilter};
+
+use super::*;
+use crate::logging::{format_record, is_journal_stream, verbosity_level, LogFilter, LogFormat};
+
+#[rstest]
+#[case(LogFormat::Text, Level::Info, "Reloaded the config", "Reloaded the config")]
+#[case(LogFormat::Text, Level::Warn, "Could not watch /tmp", "warning: Could not watch /tmp")]
+#[case(LogFormat::Text, Level::Error, "Could not open audio file\n\t NotFound", "error: Could not open audio file\n\t NotFound")]
+#[case(LogFormat::Journal, Level::Info, "Reloaded the config", "<6>Reloaded the config")]
+#[case(LogFormat::Journal, Level::Error, "Could not open audio file\n\t NotFound", "<3>Could not open audio file\n<3>\t NotFound")]
+#[case(LogFormat::Journal, Level::Trace, "BatteryStats", "<7>BatteryStats")]
+#[case(LogFormat::Json, Level::Warn, "Could not watch \"/tmp\"",
+    r#"{"timestamp":"2026-10-19T08:30:00+00:00","level":"warn","target":"spider_volt::audio","message":"Could not watch \"/tmp\""}"#)]
+fn records_are_formatted(#[case] format: LogFormat, #[case] level: Level, #[case] message: &str, #[case] expected: &str) {
+    let time = Local.with_ymd_and_hms(2026, 10, 19, 8, 30, 0).unwrap();
+    let line = format_record(format, level, "spider_volt::audio", message, time);
+    if format == LogFormat::Json {
+        // The offset depends on the local time zone
+        let expected = expected.replace("2026-10-19T08:30:00+00:00", &time.to_rfc3339());
+        assert_eq!(line, expected);
+    } else {
+        assert_eq!(line, expected);
+    }
+}
+
+#[rstest]
+#[case("", "spider_volt::audio", LevelFilter::Info)]
+#[case("debug", "spider_volt::audio", LevelFilter::Debug)]
+#[case("warn,spider_volt::audio=trace", "spider_volt::audio", LevelFilter::Trace)]
+#[case("warn,spider_volt::audio=trace", "spider_volt::audio_worker", LevelFilter::Warn)]
+#[case("warn,spider_volt::audio=trace", "spider_volt::battery_monitor", LevelFilter::Warn)]
+#[case("spider_volt=debug,spider_volt::audio=off", "spider_volt::audio", LevelFilter::Off)]
+#[case("spider_volt=debug,spider_volt::audio=off", "spider_volt::bar", LevelFilter::Debug)]
+#[case("spider_volt::audio", "spider_volt::audio", LevelFilter::Trace)]
+#[case(" error , spider_volt = WARN ", "spider_volt::main", LevelFilter::Warn)]
+fn filters_use_the_most_specific_module(#[case] spec: &str, #[case] target: &str, #[case] expected: LevelFilter) {
+    let (filter, errors) = LogFilter::parse(spec, LevelFilter::Info);
+    assert!(errors.is_empty(), "{errors:?}");
+    assert_eq!(filter.level_for(target), expected);
+}
+
+#[rstest]
+#[case("64769:1234567", true)]
+#[case("64769:7654321", false)]
+#[case("2049:1234567", false)]
+#[case("64769", false)]
+#[case("", false)]
+#[case("dev:ino", false)]
+fn journal_stream_has_to_name_stderr(#[case] journal_stream: &str, #[case] expected: bool) {
+    assert_eq!(is_journal_stream(journal_stream, 64769, 1234567), expected);
+}
+
+#[rstest]
+fn invalid_directives_are_skipped() {
+    let (filter, errors) = LogFilter::parse("debug,spider_volt::audio=loud", LevelFilter::Info);
+    assert_eq!(errors, ["Ignoring the log directive \"spider_volt::audio=loud\", \"loud\" is not a log level"]);
+    assert_eq!(filter.level_for("spider_volt::audio"), LevelFilter::Debug);
+}
+
+#[rstest]
+fn verbosity_replaces_the_default_level_only() {
+    let (filter, _) = LogFilter::parse("debug,spider_volt::audio=trace", LevelFilter::Info);
+    let filter = filter.with_default(LevelFilter::Warn);
+    assert_eq!(filter.level_for("spider_volt::bar"), LevelFilter::Warn);
+    assert_eq!(filter.level_for("spider_volt::audio"), LevelFilter::Trace);
+}
+
+#[rstest]
+#[case(0, 0, LevelFilter::Info)]
+#[case(1, 0, LevelFilter::Debug)]
+#[case(3, 0, LevelFilter::Trace)]
+#[case(0, 1, LevelFilter::Warn)]
+#[case(0, 2, LevelFilter::Error)]
+#[case(0, 3, LevelFilter::Off)]
+fn verbose_and_quiet_move_the_level(#[case] verbose: u8, #[case] quiet: u8, #[case] expected: LevelFilter) {
+    assert_eq!(verbosity_level(verbose, quiet), expected);
+}
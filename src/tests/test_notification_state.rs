@@ -0,0 +1,121 @@
+use std::{collections::{BTreeSet, HashSet}, fs, path::PathBuf};
+
+use super::*;
+use crate::state::{load_notification_state, save_notification_state, NotificationState};
+
+/// The state file in a state dir that does not exist yet
+fn state_path(temp_dir: &TempDir) -> PathBuf {
+    temp_dir.join("spider-volt/notification_state.json")
+}
+
+/// A daemon that just started, `BatteryStats::new` sets the previous state to the opposite of the current one
+fn started_battery(state: &str, percentage: i32) -> BatteryStats {
+    let previous_state = if state == "Discharging" { "Charging" } else { "Discharging" };
+    BatteryStats::parse_toml_from_str(&format!(r#"
+        prev_state = "{previous_state}"
+        last_notified_state = "{state}"
+        current_state = "{state}"
+        percentage = {percentage}
+    "#)).unwrap()
+}
+
+fn saved_state(state: &str, sent_thresholds: &[u8]) -> NotificationState {
+    NotificationState { state: state.to_string(), sent_thresholds: sent_thresholds.iter().copied().collect() }
+}
+
+const LOW_AND_HIGH_CONFIG: &str = r#"
+    [low_battery_levels.20]
+    message = "Battery Low"
+
+    [low_battery_levels.10]
+    message = "Battery Critical"
+
+    [high_battery_levels.80]
+    message = "Battery High"
+"#;
+
+#[rstest]
+fn saved_state_round_trips() {
+    let temp_dir = TempDir::new("state-round-trip");
+    let path = state_path(&temp_dir);
+    assert_eq!(load_notification_state(&path), Ok(None));
+
+    let notification_state = saved_state("Discharging", &[20, 10]);
+    save_notification_state(&path, &notification_state).unwrap();
+    assert_eq!(load_notification_state(&path), Ok(Some(notification_state)));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "{\"state\":\"Discharging\",\"sent_thresholds\":[10,20]}\n");
+}
+
+#[rstest]
+fn a_corrupt_state_file_is_an_error() {
+    let temp_dir = TempDir::new("state-corrupt");
+    let path = temp_dir.write("spider-volt/notification_state.json", "{\"state\":");
+    let error = load_notification_state(&path).unwrap_err();
+    assert!(error.starts_with("Could not parse"), "{error}");
+}
+
+#[rstest]
+fn restored_thresholds_do_not_fire_again(_clear_calls: ()) {
+    let config = Config::parse_toml_from_str(LOW_AND_HIGH_CONFIG).unwrap();
+    let mut battery = started_battery("Discharging", 8);
+    let mut battery_notif_sent = HashSet::new();
+
+    battery.restore_notification_state(saved_state("Discharging", &[20, 10]), &mut battery_notif_sent).unwrap();
+    battery.handle_charger_notifications(&config.charger_notifications, 5000);
+    battery.handle_battery_state_change(&mut battery_notif_sent);
+    battery.handle_battery(&config.low_battery_levels, &config.high_battery_levels, 5000, &mut battery_notif_sent);
+
+    assert!(get_calls().is_empty(), "{:?}", get_calls());
+    assert_eq!(battery.notification_state(&battery_notif_sent), saved_state("Discharging", &[20, 10]));
+}
+
+#[rstest]
+fn without_a_saved_state_crossed_thresholds_fire(_clear_calls: ()) {
+    let config = Config::parse_toml_from_str(LOW_AND_HIGH_CONFIG).unwrap();
+    let mut battery = started_battery("Discharging", 8);
+    let mut battery_notif_sent = HashSet::new();
+
+    battery.handle_battery_state_change(&mut battery_notif_sent);
+    battery.handle_battery(&config.low_battery_levels, &config.high_battery_levels, 5000, &mut battery_notif_sent);
+
+    // Both the 20% and the 10% notification are sent at 8%
+    assert_eq!(get_calls(), ["8", "8"]);
+}
+
+#[rstest]
+#[case("Discharging", "Charging", 8, "the battery was Discharging and is Charging now")]
+#[case("Charging", "Full", 100, "the battery was Charging and is Full now")]
+#[case("Unknown", "Unknown", 50, "the battery state is unknown")]
+fn a_state_change_while_down_discards_the_saved_state(
+    #[case] saved: &str,
+    #[case] current: &str,
+    #[case] percentage: i32,
+    #[case] expected_reason: &str,
+) {
+    let mut battery = started_battery(current, percentage);
+    let mut battery_notif_sent = HashSet::new();
+
+    let reason = battery.restore_notification_state(saved_state(saved, &[20, 10]), &mut battery_notif_sent).unwrap_err();
+    assert_eq!(reason, expected_reason);
+    assert!(battery_notif_sent.is_empty());
+    assert_eq!(battery.notification_state(&battery_notif_sent).state, current);
+}
+
+#[rstest]
+#[case("Discharging", 15, &[20, 10], &[20])]
+#[case("Discharging", 25, &[20, 10], &[])]
+#[case("Charging", 85, &[80], &[80])]
+#[case("Charging", 75, &[80], &[])]
+fn thresholds_the_battery_is_no_longer_past_are_dropped(
+    #[case] state: &str,
+    #[case] percentage: i32,
+    #[case] saved_thresholds: &[u8],
+    #[case] expected_thresholds: &[u8],
+) {
+    let mut battery = started_battery(state, percentage);
+    let mut battery_notif_sent = HashSet::new();
+
+    battery.restore_notification_state(saved_state(state, saved_thresholds), &mut battery_notif_sent).unwrap();
+    let sent_thresholds: BTreeSet<u8> = battery_notif_sent.into_iter().collect();
+    assert_eq!(sent_thresholds, expected_thresholds.iter().copied().collect());
+}
@@ -0,0 +1,148 @@
+use std::{cell::Cell, rc::Rc};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+
+use super::*;
+use crate::profile_rules::{announce_profile_switch, Clock, ProfileRule, ProfileSelector};
+
+/// A clock that only moves when the test moves it
+#[derive(Clone)]
+struct FakeClock(Rc<Cell<NaiveDateTime>>);
+
+impl FakeClock {
+    /// 2026-10-19 is a Monday
+    fn at(day: u32, hour: u32, minute: u32) -> Self {
+        let time = NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(hour, minute, 0).unwrap();
+        FakeClock(Rc::new(Cell::new(time)))
+    }
+
+    fn advance_minutes(&self, minutes: i64) {
+        self.0.set(self.0.get() + TimeDelta::minutes(minutes));
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0.get()
+    }
+}
+
+/// The profile of the first rule that matches
+fn matching_profile<'a>(profile_selector: &ProfileSelector<FakeClock>, rules: &'a [ProfileRule]) -> Option<&'a str> {
+    profile_selector.matching_rule(rules).map(|(_, rule)| rule.profile.as_str())
+}
+
+fn parse_rules(rules_str: &str) -> Vec<ProfileRule> {
+    #[derive(serde::Deserialize)]
+    struct Rules {
+        profile_rules: Vec<ProfileRule>,
+    }
+    toml::from_str::<Rules>(rules_str).unwrap().profile_rules
+}
+
+const RULES: &str = r#"
+    [[profile_rules]]
+    profile = "desk"
+    days = ["mon", "tue", "wed", "thu", "fri"]
+    start = "09:00"
+    end = "17:00"
+    docked = true
+
+    [[profile_rules]]
+    profile = "night"
+    start = "22:00"
+    end = "06:00"
+
+    [[profile_rules]]
+    profile = "weekend"
+    days = ["Saturday", "sun"]
+"#;
+
+#[rstest]
+#[case(FakeClock::at(19, 12, 0), None)]
+#[case(FakeClock::at(19, 23, 30), Some("night"))]
+#[case(FakeClock::at(20, 5, 59), Some("night"))]
+#[case(FakeClock::at(20, 6, 0), None)]
+#[case(FakeClock::at(24, 12, 0), Some("weekend"))]
+#[case(FakeClock::at(25, 23, 0), Some("night"))]
+fn rules_match_by_time_and_day(#[case] clock: FakeClock, #[case] expected: Option<&str>) {
+    let rules = parse_rules(RULES);
+    let profile_selector = ProfileSelector::new(clock);
+    assert_eq!(matching_profile(&profile_selector, &rules), expected);
+}
+
+#[rstest]
+#[case(FakeClock::at(19, 12, 0), None)]
+#[case(FakeClock::at(19, 23, 30), Some((1, "start = 22:00, end = 06:00".to_string())))]
+#[case(FakeClock::at(24, 12, 0), Some((2, "days = Saturday, sun".to_string())))]
+fn the_matching_rule_is_described_for_the_log(#[case] clock: FakeClock, #[case] expected: Option<(usize, String)>) {
+    let rules = parse_rules(RULES);
+    let profile_selector = ProfileSelector::new(clock);
+    let matching_rule = profile_selector.matching_rule(&rules).map(|(index, rule)| (index, rule.to_string()));
+    assert_eq!(matching_rule, expected);
+}
+
+#[rstest]
+fn docked_after_charging_for_most_of_the_last_hour() {
+    let rules = parse_rules(RULES);
+    let clock = FakeClock::at(19, 9, 0);
+    let mut profile_selector = ProfileSelector::new(clock.clone());
+
+    profile_selector.record_charger_state(true);
+    clock.advance_minutes(30);
+    profile_selector.record_charger_state(true);
+    assert!(!profile_selector.is_docked());
+
+    clock.advance_minutes(1);
+    profile_selector.record_charger_state(true);
+    assert!(profile_selector.is_docked());
+    assert_eq!(matching_profile(&profile_selector, &rules), Some("desk"));
+
+    // Unplugged for half an hour, only 29 of the last 60 minutes were on the charger
+    profile_selector.record_charger_state(false);
+    clock.advance_minutes(31);
+    profile_selector.record_charger_state(false);
+    assert!(!profile_selector.is_docked());
+    assert_eq!(matching_profile(&profile_selector, &rules), None);
+}
+
+#[rstest]
+fn samples_older_than_an_hour_are_dropped() {
+    let clock = FakeClock::at(19, 9, 0);
+    let mut profile_selector = ProfileSelector::new(clock.clone());
+
+    profile_selector.record_charger_state(true);
+    clock.advance_minutes(120);
+    profile_selector.record_charger_state(false);
+    clock.advance_minutes(61);
+    profile_selector.record_charger_state(false);
+
+    assert!(!profile_selector.is_docked());
+}
+
+#[rstest]
+fn invalid_rules_are_reported() {
+    let config_str = r#"
+    [profiles.desk]
+    mute_sounds = true
+
+    [[profile_rules]]
+    profile = "desk"
+    days = ["someday"]
+    start = "9am"
+
+    [[profile_rules]]
+    profile = "travel"
+    "#;
+
+    let issues = Config::parse_toml_from_str(config_str).unwrap().validate();
+    let keys: Vec<&str> = issues.iter().map(|issue| issue.key.as_str()).collect();
+
+    assert_eq!(keys, vec!["profile_rules.0", "profile_rules.0", "profile_rules.1.profile"]);
+}
+
+#[rstest]
+fn profile_switch_is_announced(_clear_calls: ()) {
+    announce_profile_switch("travel", 5000);
+    assert_eq!(get_calls(), vec!["profile travel"]);
+}
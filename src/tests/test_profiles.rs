@@ -0,0 +1,88 @@
+use super::*;
+use crate::config::{GetUrgency, UrgencyLevel};
+
+const PROFILES_CONFIG: &str = r#"
+    [low_battery_levels.10]
+    message = "Battery Critically Low"
+    notification_sound = "/sounds/critical.ogg"
+    urgent_level = "Critical"
+
+    [charger_notifications]
+    charging = true
+    plugged_sound = "/sounds/plugged.ogg"
+
+    [profiles.travel.low_battery_levels.40]
+    message = "Find a charger"
+
+    [profiles.presentation]
+    mute_sounds = true
+    minimum_urgency = "Critical"
+"#;
+
+fn config_with_profile(profile: &str) -> Config {
+    let mut config = Config::default();
+    config.layer(Config::parse_toml_from_str(PROFILES_CONFIG).unwrap());
+    assert!(config.apply_profile(profile));
+    config.resolve_inheritance();
+    config.apply_sound_and_urgency_filters();
+    config
+}
+
+#[rstest]
+fn profile_is_layered_on_top_of_the_config() {
+    let config = config_with_profile("travel");
+
+    assert_eq!(config.active_profile(), Some("travel"));
+    let low_battery_levels = config.low_battery_levels.unwrap();
+    assert_eq!(low_battery_levels.into_keys().collect::<Vec<_>>(), vec![10, 20, 40]);
+}
+
+#[rstest]
+fn presentation_profile_mutes_sounds_and_keeps_critical_alerts() {
+    let config = config_with_profile("presentation");
+
+    let low_battery_levels = config.low_battery_levels.unwrap();
+    assert_eq!(low_battery_levels.keys().collect::<Vec<_>>(), vec![&10]);
+    assert_eq!(low_battery_levels[&10].notification_sound(), "");
+    assert_eq!(low_battery_levels[&10].urgent_level(), UrgencyLevel::Critical);
+    assert!(config.charger_notifications.is_none());
+}
+
+#[rstest]
+fn unknown_profile_leaves_the_config_unchanged() {
+    let mut config = Config::parse_toml_from_str(PROFILES_CONFIG).unwrap();
+    assert!(!config.apply_profile("desk"));
+    assert_eq!(config.active_profile(), None);
+}
+
+#[rstest]
+#[case("travel", Ok(()))]
+#[case("default", Ok(()))]
+#[case("Travel", Err("Could not find the profile \"Travel\", available profiles: presentation, travel".to_string()))]
+fn profile_names_are_checked_before_they_are_saved(#[case] profile: &str, #[case] expected: Result<(), String>) {
+    let config = Config::parse_toml_from_str(PROFILES_CONFIG).unwrap();
+    assert_eq!(config.check_profile_name(profile), expected);
+}
+
+#[rstest]
+fn invalid_profiles_are_reported() {
+    let config_str = r#"
+    [profiles.default]
+    mute_sounds = true
+
+    [profiles.desk.profiles.nested]
+    mute_sounds = true
+
+    [profiles.travel.low_battery_levels.40]
+    notification_icon = "battery-alert"
+    "#;
+
+    let issues = Config::parse_toml_from_str(config_str).unwrap().validate();
+    let keys: Vec<&str> = issues.iter().map(|issue| issue.key.as_str()).collect();
+
+    assert_eq!(keys, vec![
+        "profiles.default",
+        "profiles.desk.profiles",
+        "profiles.travel.low_battery_levels.40.message",
+    ]);
+}
@@ -0,0 +1,109 @@
+use std::{fs, path::{Path, PathBuf}};
+
+use super::*;
+use crate::service::{install_service, uninstall_service, unit_file, Systemctl};
+
+/// A unit dir in a temp dir, it does not exist until the unit is installed
+struct TempUnitDir {
+    _temp_dir: TempDir,
+    path: PathBuf,
+}
+
+impl TempUnitDir {
+    fn new(name: &str) -> Self {
+        let temp_dir = TempDir::new(&format!("units-{name}"));
+        TempUnitDir { path: temp_dir.join("systemd/user"), _temp_dir: temp_dir }
+    }
+
+    fn unit(&self) -> Option<String> {
+        fs::read_to_string(self.path.join("spider-volt.service")).ok()
+    }
+}
+
+/// Records every `systemctl --user` call, `failing` is the first argument of a call that fails
+#[derive(Default)]
+struct FakeSystemctl {
+    calls: Vec<String>,
+    failing: Option<&'static str>,
+}
+
+impl Systemctl for FakeSystemctl {
+    fn run(&mut self, arguments: &[&str]) -> Result<(), String> {
+        self.calls.push(arguments.join(" "));
+        match self.failing {
+            Some(failing) if arguments.first() == Some(&failing) => Err(format!("{failing} failed")),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[rstest]
+fn the_shipped_unit_is_the_generated_one() {
+    assert_eq!(include_str!("../../systemd/spider-volt.service"), unit_file(Path::new("/usr/bin/spider-volt")));
+}
+
+#[rstest]
+#[case("/home/me/.cargo/bin/spider-volt", "ExecStart=/home/me/.cargo/bin/spider-volt\n")]
+#[case("/home/me/my apps/spider-volt", "ExecStart=\"/home/me/my apps/spider-volt\"\n")]
+#[case("/opt/100%/spider-volt", "ExecStart=/opt/100%%/spider-volt\n")]
+fn exec_start_is_the_binary_path(#[case] binary_path: &str, #[case] expected: &str) {
+    let unit = unit_file(Path::new(binary_path));
+    assert!(unit.contains(expected), "{unit}");
+    assert!(unit.contains("After=graphical-session.target\n") && unit.contains("WantedBy=graphical-session.target\n"));
+}
+
+#[rstest]
+#[case(false, vec!["daemon-reload"])]
+#[case(true, vec!["daemon-reload", "enable --now spider-volt.service"])]
+fn installing_writes_the_unit_and_reloads_systemd(#[case] enable: bool, #[case] expected_calls: Vec<&str>) {
+    let unit_dir = TempUnitDir::new(&format!("install-{enable}"));
+    let binary_path = Path::new("/home/me/.cargo/bin/spider-volt");
+    let mut systemctl = FakeSystemctl::default();
+
+    install_service(&unit_dir.path, binary_path, enable, &mut systemctl).unwrap();
+
+    assert_eq!(unit_dir.unit(), Some(unit_file(binary_path)));
+    assert_eq!(systemctl.calls, expected_calls);
+}
+
+#[rstest]
+fn installing_again_replaces_a_unit_for_another_binary() {
+    let unit_dir = TempUnitDir::new("reinstall");
+    install_service(&unit_dir.path, Path::new("/usr/bin/spider-volt"), false, &mut FakeSystemctl::default()).unwrap();
+    install_service(&unit_dir.path, Path::new("/usr/local/bin/spider-volt"), false, &mut FakeSystemctl::default()).unwrap();
+    assert_eq!(unit_dir.unit(), Some(unit_file(Path::new("/usr/local/bin/spider-volt"))));
+}
+
+#[rstest]
+fn a_failing_systemctl_is_an_error() {
+    let unit_dir = TempUnitDir::new("failing");
+    let mut systemctl = FakeSystemctl { failing: Some("enable"), ..FakeSystemctl::default() };
+
+    let error = install_service(&unit_dir.path, Path::new("/usr/bin/spider-volt"), true, &mut systemctl).unwrap_err();
+    assert_eq!(error, "enable failed");
+    // The unit is still written, it can be enabled by hand
+    assert!(unit_dir.unit().is_some());
+}
+
+#[rstest]
+fn uninstalling_disables_and_removes_the_unit() {
+    let unit_dir = TempUnitDir::new("uninstall");
+    install_service(&unit_dir.path, Path::new("/usr/bin/spider-volt"), true, &mut FakeSystemctl::default()).unwrap();
+    // A unit that was never enabled makes `disable` fail, it is removed anyway
+    let mut systemctl = FakeSystemctl { failing: Some("disable"), ..FakeSystemctl::default() };
+
+    uninstall_service(&unit_dir.path, &mut systemctl).unwrap();
+
+    assert_eq!(unit_dir.unit(), None);
+    assert_eq!(systemctl.calls, ["disable --now spider-volt.service", "daemon-reload"]);
+}
+
+#[rstest]
+fn uninstalling_a_missing_unit_is_an_error() {
+    let unit_dir = TempUnitDir::new("missing");
+    let mut systemctl = FakeSystemctl::default();
+
+    let error = uninstall_service(&unit_dir.path, &mut systemctl).unwrap_err();
+    assert!(error.contains("the service is not installed"), "{error}");
+    assert!(systemctl.calls.is_empty());
+}
@@ -0,0 +1,32 @@
+use signal_hook::{consts::{SIGINT, SIGUSR1}, low_level::raise};
+
+use super::*;
+use crate::shutdown::{finish_notifications, Signals};
+
+#[rstest]
+fn sigusr1_requests_the_status_once() {
+    let signals = Signals::register();
+    assert!(!signals.status_requested());
+
+    raise(SIGUSR1).unwrap();
+    assert!(signals.status_requested());
+    assert!(!signals.status_requested());
+    assert_eq!(signals.shutdown_requested(), None);
+}
+
+// Raised only once in the whole test run, a second SIGINT exits the process like it does for the daemon
+#[rstest]
+fn sigint_stops_the_daemon_loop() {
+    let signals = Signals::register();
+    assert_eq!(signals.shutdown_requested(), None);
+
+    raise(SIGINT).unwrap();
+    assert_eq!(signals.shutdown_requested(), Some("SIGINT"));
+    assert!(!signals.status_requested());
+}
+
+#[rstest]
+fn sounds_finish_before_the_notification_is_closed(_clear_calls: ()) {
+    finish_notifications();
+    assert_eq!(get_calls(), ["drain audio", "close notification"]);
+}
@@ -0,0 +1,70 @@
+use std::collections::HashSet;
+
+use chrono::{Local, TimeZone};
+
+use super::*;
+use crate::status::{battery_status, format_status, StatusReport, StatusSource};
+
+fn battery_stats(current_state: &str, time_remaining: &str) -> BatteryStats {
+    BatteryStats::parse_toml_from_str(&format!(r#"
+        prev_state = "{current_state}"
+        last_notified_state = "{current_state}"
+        current_state = "{current_state}"
+        percentage = 42
+        {time_remaining}
+    "#)).unwrap()
+}
+
+#[rstest]
+fn status_lists_fired_thresholds_in_order() {
+    let snoozed_until = Local.with_ymd_and_hms(2026, 10, 19, 14, 30, 0).unwrap();
+    let status = battery_status(&battery_stats("Discharging", ""), "quiet", &HashSet::from([10, 30, 20]), Some(snoozed_until));
+
+    assert_eq!(status.fired_thresholds, [10, 20, 30]);
+    assert_eq!(status.profile, "quiet");
+    assert_eq!(status.snoozed_until, Some(snoozed_until.to_rfc3339()));
+}
+
+#[rstest]
+#[case("Discharging", "time_remaining = 4980", "Remaining: 1h 23m until empty")]
+#[case("Charging", "time_remaining = 720", "Remaining: 12m until full")]
+#[case("Discharging", "", "Remaining: unknown")]
+fn time_remaining_is_readable(#[case] current_state: &str, #[case] time_remaining: &str, #[case] expected: &str) {
+    let status = battery_status(&battery_stats(current_state, time_remaining), "default", &HashSet::new(), None);
+    let output = format_status(&StatusReport { source: StatusSource::Daemon, status });
+    assert!(output.contains(expected), "{output}");
+}
+
+#[rstest]
+fn daemon_status_is_formatted() {
+    let snoozed_until = Local.with_ymd_and_hms(2026, 10, 19, 14, 30, 0).unwrap();
+    let status = battery_status(&battery_stats("Discharging", ""), "default", &HashSet::from([20, 10]), Some(snoozed_until));
+
+    assert_eq!(format_status(&StatusReport { source: StatusSource::Daemon, status }), "\
+        Battery:   42%, Discharging\n\
+        Remaining: unknown\n\
+        Profile:   default\n\
+        Fired:     10%, 20%\n\
+        Snoozed:   until 14:30\n");
+}
+
+#[rstest]
+fn standalone_status_does_not_guess() {
+    let status = battery_status(&battery_stats("Discharging", ""), "default", &HashSet::new(), None);
+    let output = format_status(&StatusReport { source: StatusSource::Standalone, status });
+    assert!(output.contains("Fired:     unknown with --standalone"), "{output}");
+    assert!(output.contains("Snoozed:   unknown with --standalone"), "{output}");
+}
+
+#[rstest]
+fn json_status_names_its_source() {
+    let status = battery_status(&battery_stats("Charging", "time_remaining = 60"), "default", &HashSet::from([80]), None);
+    let json: serde_json::Value = serde_json::to_value(StatusReport { source: StatusSource::Standalone, status }).unwrap();
+
+    assert_eq!(json["source"], "standalone");
+    assert_eq!(json["percentage"], 42);
+    assert_eq!(json["state"], "Charging");
+    assert_eq!(json["time_remaining"], 60);
+    assert_eq!(json["fired_thresholds"], serde_json::json!([80]));
+    assert_eq!(json["snoozed_until"], serde_json::Value::Null);
+}
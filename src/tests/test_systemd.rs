@@ -0,0 +1,109 @@
+use std::{
+    os::{linux::net::SocketAddrExt, unix::net::{SocketAddr, UnixDatagram}},
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use super::*;
+use crate::control_socket::DaemonStatus;
+use crate::systemd::{watchdog_interval, SystemdNotifier};
+
+/// A stand-in for systemd's notify socket, in a temp dir
+struct NotifySocket {
+    _temp_dir: TempDir,
+    path: PathBuf,
+    socket: UnixDatagram,
+}
+
+impl NotifySocket {
+    fn new(name: &str) -> Self {
+        let temp_dir = TempDir::new(&format!("notify-{name}"));
+        let path = temp_dir.join("notify");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        NotifySocket { _temp_dir: temp_dir, path, socket }
+    }
+
+    /// Every message sent so far
+    fn messages(&self) -> Vec<String> {
+        let mut messages = Vec::new();
+        let mut buffer = [0; 1024];
+        while let Ok(length) = self.socket.recv(&mut buffer) {
+            messages.push(String::from_utf8_lossy(&buffer[..length]).to_string());
+        }
+        messages
+    }
+}
+
+fn status(percentage: i32, snoozed: bool) -> DaemonStatus {
+    DaemonStatus {
+        percentage,
+        state: "Discharging".to_string(),
+        time_remaining: None,
+        profile: "default".to_string(),
+        fired_thresholds: Vec::new(),
+        snoozed_until: snoozed.then(|| "2026-10-19T22:00:00+02:00".to_string()),
+    }
+}
+
+#[rstest]
+fn readiness_and_status_changes_are_sent() {
+    let notify_socket = NotifySocket::new("status");
+    let mut notifier = SystemdNotifier::connect(notify_socket.path.to_str().unwrap(), None).unwrap();
+
+    notifier.ready(&status(42, false));
+    notifier.status(&status(42, false));
+    notifier.status(&status(41, false));
+    notifier.status(&status(41, true));
+    notifier.watchdog();
+    notifier.stopping();
+
+    assert_eq!(notify_socket.messages(), [
+        "READY=1\nSTATUS=42%, Discharging, profile default",
+        "STATUS=41%, Discharging, profile default",
+        "STATUS=41%, Discharging, profile default, snoozed",
+        "STOPPING=1",
+    ]);
+}
+
+#[rstest]
+fn the_watchdog_is_pinged_once_per_interval() {
+    let notify_socket = NotifySocket::new("watchdog");
+    let mut notifier = SystemdNotifier::connect(notify_socket.path.to_str().unwrap(), Some(Duration::from_millis(100))).unwrap();
+
+    notifier.watchdog();
+    notifier.watchdog();
+    assert_eq!(notify_socket.messages(), ["WATCHDOG=1"]);
+
+    thread::sleep(Duration::from_millis(100));
+    notifier.watchdog();
+    assert_eq!(notify_socket.messages(), ["WATCHDOG=1"]);
+}
+
+#[rstest]
+fn abstract_notify_sockets_are_supported() {
+    let name = format!("spider-volt-notify-abstract-{}", std::process::id());
+    let socket = UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+    let mut notifier = SystemdNotifier::connect(&format!("@{name}"), None).unwrap();
+
+    notifier.stopping();
+    let mut buffer = [0; 64];
+    let length = socket.recv(&mut buffer).unwrap();
+    assert_eq!(&buffer[..length], b"STOPPING=1");
+}
+
+#[rstest]
+#[case(Some("30000000"), None, Some(Duration::from_secs(15)))]
+#[case(Some("30000000"), Some("1234"), Some(Duration::from_secs(15)))]
+#[case(Some("30000000"), Some("4321"), None)]
+#[case(Some("0"), None, None)]
+#[case(Some("soon"), None, None)]
+#[case(None, Some("1234"), None)]
+fn watchdog_interval_is_half_of_watchdog_usec(
+        #[case] watchdog_usec: Option<&str>,
+        #[case] watchdog_pid: Option<&str>,
+        #[case] expected: Option<Duration>
+    ) {
+    assert_eq!(watchdog_interval(watchdog_usec, watchdog_pid, 1234), expected);
+}